@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use rocket::{
+    http::Header,
+    response::Responder,
+    serde::json::{self, Value},
+    Request,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, parse_events, store, Event, Semester};
+
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+
+/// Stale-while-revalidate cache of the upstream feeds.
+///
+/// Successful responses are refreshed once they are older than the ttl, but kept around so they
+/// can still be served if the refresh fails. Failures are never cached.
+///
+/// Events that disappear from a feed are served as cancelled for the grace period, so
+/// subscribers notice instead of the event silently vanishing.
+///
+/// If a directory is configured, every fetched feed is also written to disk and loaded again on
+/// startup, so a restart neither refetches all feeds nor loses semesters the upstream dropped.
+pub struct FeedCache {
+    ttl: Duration,
+    grace_period: TimeDelta,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<(String, Semester), Entry>>,
+}
+
+struct Entry {
+    feed: String,
+    events: Vec<Event>,
+    cancelled: Vec<(Cancellation, Event)>,
+    fetched_at: SystemTime,
+}
+
+/// An event that disappeared from the upstream feed.
+#[derive(Clone, Serialize, Deserialize)]
+struct Cancellation {
+    /// Time the event was first missing in UTC.
+    since: NaiveDateTime,
+    /// The event as last sent by the upstream.
+    event: Value,
+}
+
+/// On-disk format, keeps the raw upstream feed so it is parsed exactly like a fresh response.
+#[derive(Serialize, Deserialize)]
+struct StoredFeed {
+    academic_year: String,
+    semester: Semester,
+    feed: String,
+    #[serde(default)]
+    cancelled: Vec<Cancellation>,
+}
+
+/// Value served from the [`FeedCache`], `stale` if at least one feed could not be refreshed.
+///
+/// `warnings` about the request are sent along as `Warning` headers.
+pub struct Cached<T> {
+    pub value: T,
+    pub stale: bool,
+    pub warnings: Vec<String>,
+}
+
+impl FeedCache {
+    pub fn new(ttl: Duration, grace_period: TimeDelta) -> Self {
+        Self {
+            ttl,
+            grace_period,
+            dir: None,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Creates a cache persisted in `dir`, loading every feed stored there.
+    pub fn load(ttl: Duration, grace_period: TimeDelta, dir: PathBuf) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut entries = HashMap::new();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                match load_entry(&path) {
+                    Ok((key, entry)) => {
+                        entries.insert(key, entry);
+                    }
+                    Err(error) => warn_!("ignoring cached feed {}: {error}", path.display()),
+                }
+            }
+        }
+        Ok(Self {
+            ttl,
+            grace_period,
+            dir: Some(dir),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Serves the feed of `academic_year` in `semester`, the last day of which is `end`.
+    pub async fn get_or_fetch(
+        &self,
+        academic_year: &str,
+        semester: Semester,
+        end: NaiveDate,
+        fetch: impl Future<Output = Result<String, Error>>,
+    ) -> Result<Cached<Vec<Event>>, Error> {
+        let key = (academic_year.to_string(), semester);
+        if let Some(entry) = self.entries.lock().unwrap().get(&key) {
+            if entry.fetched_at.elapsed().is_ok_and(|age| age < self.ttl) {
+                return Ok(Cached::fresh(entry.serve(self.grace_period)));
+            }
+        }
+        let refreshed = match fetch.await {
+            Ok(feed) => parse_events(&feed).map(|events| (feed, events)),
+            Err(error) => Err(error),
+        };
+        match refreshed {
+            Ok((feed, events)) => {
+                let ended = end < Utc::now().date_naive();
+                Ok(Cached::fresh(self.store(key, feed, events, ended).await))
+            }
+            Err(error) => match self.entries.lock().unwrap().get(&key) {
+                Some(entry) => {
+                    warn_!("serving stale events for {} {}: {error}", key.0, key.1);
+                    Ok(Cached {
+                        value: entry.serve(self.grace_period),
+                        stale: true,
+                        warnings: Vec::new(),
+                    })
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    /// Remembers a successfully fetched feed and returns the events to serve.
+    ///
+    /// Once a semester has ended the upstream tends to drop its events, so an empty feed does
+    /// not replace events we already know about.
+    async fn store(
+        &self,
+        key: (String, Semester),
+        feed: String,
+        events: Vec<Event>,
+        ended: bool,
+    ) -> Vec<Event> {
+        let (served, stored) = {
+            let mut entries = self.entries.lock().unwrap();
+            let previous = entries.get_mut(&key);
+            if let Some(entry) = previous.filter(|_| events.is_empty() && ended) {
+                entry.fetched_at = SystemTime::now();
+                return entry.serve(self.grace_period);
+            }
+            let entry = Entry {
+                cancelled: entries
+                    .get(&key)
+                    .map(|previous| previous.cancellations(&events, self.grace_period))
+                    .unwrap_or_default(),
+                feed,
+                events,
+                fetched_at: SystemTime::now(),
+            };
+            let served = entry.serve(self.grace_period);
+            let stored = self.dir.as_ref().map(|_| StoredFeed {
+                academic_year: key.0.clone(),
+                semester: key.1.clone(),
+                feed: entry.feed.clone(),
+                cancelled: entry
+                    .cancelled
+                    .iter()
+                    .map(|(cancellation, _)| cancellation.clone())
+                    .collect(),
+            });
+            entries.insert(key, entry);
+            (served, stored)
+        };
+        if let (Some(dir), Some(stored)) = (&self.dir, stored) {
+            if let Err(error) = persist(dir, stored).await {
+                warn_!("failed to persist feed: {error}");
+            }
+        }
+        served
+    }
+}
+
+impl Entry {
+    /// The events of the feed plus those cancelled within the grace period.
+    fn serve(&self, grace_period: TimeDelta) -> Vec<Event> {
+        let now = Utc::now().naive_utc();
+        self.events
+            .iter()
+            .cloned()
+            .chain(
+                self.cancelled
+                    .iter()
+                    .filter(|(cancellation, _)| cancellation.since + grace_period > now)
+                    .map(|(_, event)| Event {
+                        is_cancelled: true,
+                        ..event.clone()
+                    }),
+            )
+            .collect()
+    }
+
+    /// Cancellations to keep once `events` replace the events of this entry: every event that is
+    /// missing from `events`, either since this refresh or still within the grace period.
+    ///
+    /// Identities are counted, so if one of two parallel groups disappears it is cancelled too.
+    fn cancellations(
+        &self,
+        events: &[Event],
+        grace_period: TimeDelta,
+    ) -> Vec<(Cancellation, Event)> {
+        let now = Utc::now().naive_utc();
+        let mut remaining: HashMap<_, usize> = HashMap::new();
+        for event in events {
+            *remaining.entry(event.identity()).or_default() += 1;
+        }
+        let mut is_missing = |event: &Event| match remaining.get_mut(&event.identity()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        };
+        let previous: Vec<Value> = json::from_str(&self.feed).unwrap_or_default();
+        let mut cancelled = Vec::new();
+        for value in previous {
+            let Ok(event) = Event::deserialize(&value) else {
+                continue;
+            };
+            if is_missing(&event) {
+                let cancellation = Cancellation {
+                    since: now,
+                    event: value,
+                };
+                cancelled.push((cancellation, event));
+            }
+        }
+        cancelled.extend(
+            self.cancelled
+                .iter()
+                .filter(|(cancellation, event)| {
+                    cancellation.since + grace_period > now && is_missing(event)
+                })
+                .cloned(),
+        );
+        cancelled
+    }
+}
+
+fn file_name((academic_year, semester): (&str, &Semester)) -> String {
+    let academic_year: String = academic_year
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let season = if semester.winter_semester {
+        "winter"
+    } else {
+        "summer"
+    };
+    format!("{}-{season}-{academic_year}.json", semester.year)
+}
+
+fn load_entry(path: &Path) -> Result<((String, Semester), Entry), Box<dyn std::error::Error>> {
+    let stored: StoredFeed = json::from_str(&fs::read_to_string(path)?)?;
+    let cancelled = stored
+        .cancelled
+        .into_iter()
+        .map(|cancellation| {
+            let event = Event::deserialize(&cancellation.event)?;
+            Ok((cancellation, event))
+        })
+        .collect::<Result<_, json::serde_json::Error>>()?;
+    let entry = Entry {
+        events: parse_events(&stored.feed)?,
+        feed: stored.feed,
+        cancelled,
+        fetched_at: fs::metadata(path)?.modified()?,
+    };
+    Ok(((stored.academic_year, stored.semester), entry))
+}
+
+async fn persist(dir: &Path, stored: StoredFeed) -> std::io::Result<()> {
+    let path = dir.join(file_name((&stored.academic_year, &stored.semester)));
+    store::write_atomically(&path, json::to_string(&stored)?).await
+}
+
+impl<T> Cached<T> {
+    pub fn fresh(value: T) -> Self {
+        Self {
+            value,
+            stale: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Cached<U> {
+        Cached {
+            value: f(self.value),
+            stale: self.stale,
+            warnings: self.warnings,
+        }
+    }
+
+    /// Merges another cached value into this one, staying stale if either was.
+    pub fn append<E>(&mut self, other: Cached<impl IntoIterator<Item = E>>)
+    where
+        T: Extend<E>,
+    {
+        self.value.extend(other.value);
+        self.stale |= other.stale;
+        self.warnings.extend(other.warnings);
+    }
+}
+
+impl<T: Default> Default for Cached<T> {
+    fn default() -> Self {
+        Self::fresh(T::default())
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Cached<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'o> {
+        let mut response = self.value.respond_to(request)?;
+        if self.stale {
+            response.set_header(Header::new("Warning", STALE_WARNING));
+        }
+        for warning in self.warnings {
+            response.adjoin_header(Header::new("Warning", format!("299 - \"{warning}\"")));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,260 @@
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{Datelike, NaiveDate, TimeDelta};
+
+use reqwest::Url;
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+use crate::error::Error;
+
+const DEFAULT_CACHE_TTL: u64 = 900; // 900s = 15*60s = 15min
+const DEFAULT_CANCELLATION_GRACE_DAYS: u16 = 14;
+const DEFAULT_MAX_SUBSCRIPTIONS: usize = 10_000;
+/// Longest semester window override, a year.
+const MAX_WINDOW_DAYS: i64 = 366;
+/// Last year of the apprenticeship a feed can be attended in, leaving room for extensions.
+const MAX_COHORT_YEAR: u8 = 6;
+const DEFAULT_UID_DOMAIN: &str = "matse.morbatex.com";
+const DEFAULT_SCHEDULE_URL: &str =
+    "https://www.matse.itc.rwth-aachen.de/stundenplan/web/eventFeed/";
+const DEFAULT_WINTER_SEMESTER: [(u32, u32); 2] = [(9, 1), (3, 15)];
+const DEFAULT_SUMMER_SEMESTER: [(u32, u32); 2] = [(3, 1), (9, 15)];
+const DEFAULT_ACADEMIC_YEARS: [(&str, &str, Option<u8>); 4] = [
+    ("1", "1. Lehrjahr", Some(1)),
+    ("2", "2. Lehrjahr", Some(2)),
+    ("3", "3. Lehrjahr", Some(3)),
+    ("4", "Wahlpflicht", None),
+];
+
+/// Upstream schedule source, read from Rocket's figment (`Rocket.toml` or `ROCKET_*` env vars).
+#[derive(Clone, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(default = "default_schedule_url", deserialize_with = "base_url")]
+    pub schedule_url: Url,
+    #[serde(default = "default_academic_years")]
+    pub academic_years: Vec<AcademicYear>,
+    /// Seconds after which cached feeds are refreshed.
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+    /// Directory the fetched feeds are persisted in, kept in memory only if unset.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Domain part of the generated event UIDs.
+    #[serde(default = "default_uid_domain")]
+    pub uid_domain: String,
+    /// Days an event that disappeared from the upstream is still published as cancelled.
+    #[serde(default = "default_cancellation_grace_days")]
+    pub cancellation_grace_days: u16,
+    /// Number of subscription links stored at most.
+    #[serde(default = "default_max_subscriptions")]
+    pub max_subscriptions: usize,
+    #[serde(default)]
+    pub semester_windows: SemesterWindows,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AcademicYear {
+    pub id: String,
+    pub name: String,
+    /// Year of the apprenticeship in which a cohort attends this feed, all years if unset.
+    #[serde(default, deserialize_with = "cohort_year")]
+    pub cohort_year: Option<u8>,
+}
+
+/// Date ranges fetched from the upstream for each semester.
+///
+/// The defaults include the exams after the lecture period, consecutive semesters overlap.
+#[derive(Clone, Deserialize)]
+pub struct SemesterWindows {
+    /// First and last day of winter semesters as `MM-DD`, the last one in the following year.
+    #[serde(default = "default_winter_semester", deserialize_with = "month_days")]
+    pub winter: [(u32, u32); 2],
+    /// First and last day of summer semesters as `MM-DD`.
+    #[serde(default = "default_summer_semester", deserialize_with = "month_days")]
+    pub summer: [(u32, u32); 2],
+    /// Windows of single semesters, e.g. for early exams or block courses.
+    #[serde(default, deserialize_with = "overrides")]
+    pub overrides: Vec<SemesterWindow>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SemesterWindow {
+    pub year: i32,
+    pub winter_semester: bool,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl SemesterWindows {
+    /// First and last day of the semester, `None` only for years chrono cannot represent.
+    ///
+    /// A configured 02-29 falls back to 02-28 in years that are not leap years.
+    pub fn window(&self, year: i32, winter_semester: bool) -> Option<(NaiveDate, NaiveDate)> {
+        if let Some(window) = self
+            .overrides
+            .iter()
+            .find(|window| window.year == year && window.winter_semester == winter_semester)
+        {
+            return Some((window.start, window.end));
+        }
+        let ([(start_month, start_day), (end_month, end_day)], end_year) = if winter_semester {
+            (self.winter, year + 1)
+        } else {
+            (self.summer, year)
+        };
+        Some((
+            month_day(year, start_month, start_day)?,
+            month_day(end_year, end_month, end_day)?,
+        ))
+    }
+}
+
+fn month_day(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    match NaiveDate::from_ymd_opt(year, month, day) {
+        None if (month, day) == (2, 29) => NaiveDate::from_ymd_opt(year, 2, 28),
+        date => date,
+    }
+}
+
+impl Default for SemesterWindows {
+    fn default() -> Self {
+        Self {
+            winter: default_winter_semester(),
+            summer: default_summer_semester(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    pub fn feed_url(&self, academic_year: &AcademicYear) -> Result<Url, Error> {
+        self.schedule_url.join(&academic_year.id).map_err(|error| {
+            Error::Config(format!("invalid feed id {}: {error}", academic_year.id))
+        })
+    }
+
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl)
+    }
+
+    pub fn cancellation_grace_period(&self) -> TimeDelta {
+        TimeDelta::days(self.cancellation_grace_days.into())
+    }
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            schedule_url: default_schedule_url(),
+            academic_years: default_academic_years(),
+            cache_ttl: default_cache_ttl(),
+            cache_dir: None,
+            uid_domain: default_uid_domain(),
+            cancellation_grace_days: default_cancellation_grace_days(),
+            max_subscriptions: default_max_subscriptions(),
+            semester_windows: SemesterWindows::default(),
+        }
+    }
+}
+
+fn default_schedule_url() -> Url {
+    Url::parse(DEFAULT_SCHEDULE_URL).unwrap()
+}
+
+fn default_academic_years() -> Vec<AcademicYear> {
+    DEFAULT_ACADEMIC_YEARS
+        .iter()
+        .map(|(id, name, cohort_year)| AcademicYear {
+            id: id.to_string(),
+            name: name.to_string(),
+            cohort_year: *cohort_year,
+        })
+        .collect()
+}
+
+fn default_cache_ttl() -> u64 {
+    DEFAULT_CACHE_TTL
+}
+
+fn default_uid_domain() -> String {
+    DEFAULT_UID_DOMAIN.to_string()
+}
+
+fn default_cancellation_grace_days() -> u16 {
+    DEFAULT_CANCELLATION_GRACE_DAYS
+}
+
+fn default_max_subscriptions() -> usize {
+    DEFAULT_MAX_SUBSCRIPTIONS
+}
+
+fn default_winter_semester() -> [(u32, u32); 2] {
+    DEFAULT_WINTER_SEMESTER
+}
+
+fn default_summer_semester() -> [(u32, u32); 2] {
+    DEFAULT_SUMMER_SEMESTER
+}
+
+/// Parses the first and last day of a semester, given as `["MM-DD", "MM-DD"]`.
+fn month_days<'de, D>(deserializer: D) -> Result<[(u32, u32); 2], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let [start, end] = <[String; 2]>::deserialize(deserializer)?;
+    let parse = |month_day: &str| {
+        // a leap year, so that 02-29 is accepted
+        NaiveDate::parse_from_str(&format!("2000-{month_day}"), "%Y-%m-%d")
+            .map(|date| (date.month(), date.day()))
+            .map_err(|error| D::Error::custom(format!("invalid day {month_day}: {error}")))
+    };
+    Ok([parse(&start)?, parse(&end)?])
+}
+
+/// Parses the semester window overrides, which have to end after they start and be at most a
+/// year long.
+fn overrides<'de, D>(deserializer: D) -> Result<Vec<SemesterWindow>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let overrides = Vec::<SemesterWindow>::deserialize(deserializer)?;
+    for SemesterWindow { start, end, .. } in &overrides {
+        if start > end {
+            return Err(D::Error::custom(format!(
+                "semester window starts on {start} after it ends on {end}"
+            )));
+        }
+        if *end - *start > TimeDelta::days(MAX_WINDOW_DAYS) {
+            return Err(D::Error::custom(format!(
+                "semester window from {start} to {end} is longer than {MAX_WINDOW_DAYS} days"
+            )));
+        }
+    }
+    Ok(overrides)
+}
+
+/// Parses the year of the apprenticeship a feed is attended in, counted from 1.
+fn cohort_year<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(year) if !(1..=MAX_COHORT_YEAR).contains(&year) => Err(D::Error::custom(format!(
+            "cohort_year {year} is not between 1 and {MAX_COHORT_YEAR}"
+        ))),
+        year => Ok(year),
+    }
+}
+
+/// Parses the feed base url, making sure it ends with a `/` so that feed ids are appended
+/// instead of replacing the last path segment.
+fn base_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut url = String::deserialize(deserializer)?;
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    Url::parse(&url).map_err(D::Error::custom)
+}
@@ -0,0 +1,212 @@
+use std::io::Cursor;
+
+use chrono::{Datelike, NaiveDate, TimeDelta, Weekday};
+use reqwest::header::CONTENT_DISPOSITION;
+use rocket::{
+    http::{ContentType, Header},
+    response::Responder,
+    Request, Response,
+};
+
+use crate::{Event, EventType};
+
+/// Spreadsheet export of events, one row per event in chronological order.
+pub struct Csv {
+    pub events: Vec<Event>,
+    pub options: CsvOptions,
+}
+
+/// `/calendar.csv` query parameters.
+#[derive(FromForm)]
+pub struct CsvOptions {
+    /// Columns in the requested order, all of them if none are given.
+    columns: Vec<Column>,
+    #[field(default = Language::German)]
+    language: Language,
+}
+
+#[derive(Clone, Copy, FromFormField)]
+enum Column {
+    Date,
+    Weekday,
+    Start,
+    End,
+    Course,
+    Type,
+    Room,
+    Lecturer,
+}
+
+/// Language of headers, weekdays and event types. German uses `;` as delimiter and `dd.mm.yyyy`
+/// dates, as expected by spreadsheet applications in German locales.
+#[derive(Clone, Copy, PartialEq, Eq, FromFormField)]
+enum Language {
+    #[field(value = "de")]
+    German,
+    #[field(value = "en")]
+    English,
+}
+
+const ALL_COLUMNS: [Column; 8] = [
+    Column::Date,
+    Column::Weekday,
+    Column::Start,
+    Column::End,
+    Column::Course,
+    Column::Type,
+    Column::Room,
+    Column::Lecturer,
+];
+
+impl Column {
+    fn header(self, language: Language) -> &'static str {
+        match (self, language) {
+            (Column::Date, Language::German) => "Datum",
+            (Column::Date, Language::English) => "Date",
+            (Column::Weekday, Language::German) => "Wochentag",
+            (Column::Weekday, Language::English) => "Weekday",
+            (Column::Start, Language::German) => "Beginn",
+            (Column::Start, Language::English) => "Start",
+            (Column::End, Language::German) => "Ende",
+            (Column::End, Language::English) => "End",
+            (Column::Course, Language::German) => "Veranstaltung",
+            (Column::Course, Language::English) => "Course",
+            (Column::Type, Language::German) => "Art",
+            (Column::Type, Language::English) => "Type",
+            (Column::Room, Language::German) => "Raum",
+            (Column::Room, Language::English) => "Room",
+            (Column::Lecturer, Language::German) => "Dozent",
+            (Column::Lecturer, Language::English) => "Lecturer",
+        }
+    }
+
+    /// All-day events have no start time and their last day as end.
+    fn value(self, event: &Event, language: Language) -> String {
+        let (start, end) = (&event.period.start, &event.period.end);
+        match self {
+            Column::Date => date(start.date_naive(), language),
+            Column::Weekday => weekday(start.weekday(), language).to_string(),
+            Column::Start if !event.is_all_day => start.format("%H:%M").to_string(),
+            Column::Start => String::new(),
+            Column::End if !event.is_all_day => end.format("%H:%M").to_string(),
+            Column::End => date(event.period.dates().1 - TimeDelta::days(1), language),
+            Column::Course => event.name.clone(),
+            Column::Type => event_type(event, language).to_string(),
+            Column::Room => event
+                .location
+                .to_string()
+                .lines()
+                .collect::<Vec<_>>()
+                .join(", "),
+            Column::Lecturer => event
+                .lecturer
+                .name
+                .clone()
+                .or_else(|| event.lecturer.mail.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn date(date: NaiveDate, language: Language) -> String {
+    match language {
+        Language::German => date.format("%d.%m.%Y"),
+        Language::English => date.format("%Y-%m-%d"),
+    }
+    .to_string()
+}
+
+fn weekday(weekday: Weekday, language: Language) -> &'static str {
+    const GERMAN: [&str; 7] = [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ];
+    const ENGLISH: [&str; 7] = [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ];
+    let index = weekday.num_days_from_monday() as usize;
+    match language {
+        Language::German => GERMAN[index],
+        Language::English => ENGLISH[index],
+    }
+}
+
+fn event_type(event: &Event, language: Language) -> &'static str {
+    let (german, english) = match event.event_type() {
+        EventType::Lecture => ("Vorlesung", "Lecture"),
+        EventType::Exercise => ("Übung", "Exercise"),
+        EventType::Exam => ("Prüfung", "Exam"),
+        EventType::Holiday => ("Ferien", "Holiday"),
+        EventType::Other => ("Sonstiges", "Other"),
+    };
+    match language {
+        Language::German => german,
+        Language::English => english,
+    }
+}
+
+/// Quotes a field if necessary, see RFC 4180.
+fn field(value: &str, delimiter: char) -> String {
+    if value.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Csv {
+    /// The cancelled events are left out, the spreadsheet only lists what takes place.
+    fn render(mut self) -> String {
+        let language = self.options.language;
+        let delimiter = match language {
+            Language::German => ';',
+            Language::English => ',',
+        };
+        let columns = if self.options.columns.is_empty() {
+            ALL_COLUMNS.to_vec()
+        } else {
+            self.options.columns
+        };
+        let row = |values: Vec<String>| {
+            let fields: Vec<_> = values.iter().map(|value| field(value, delimiter)).collect();
+            fields.join(&delimiter.to_string()) + "\r\n"
+        };
+        self.events.retain(|event| !event.is_cancelled);
+        self.events.sort_by_key(|event| event.period.start);
+        let mut csv = row(columns
+            .iter()
+            .map(|column| column.header(language).to_string())
+            .collect());
+        for event in &self.events {
+            csv.push_str(&row(columns
+                .iter()
+                .map(|column| column.value(event, language))
+                .collect()));
+        }
+        csv
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Csv {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        Response::build()
+            .header(ContentType::CSV)
+            .header(Header::new(
+                CONTENT_DISPOSITION.as_str(),
+                " attachment; filename=\"calendar.csv\"",
+            ))
+            .sized_body(None, Cursor::new(self.render()))
+            .ok()
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+
+use rocket::{form, http::Status, response::Responder, serde::json::Json, Request};
+use serde::Serialize;
+
+/// Failure while serving a request, mostly caused by the upstream eventFeed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The upstream server could not be reached or the connection broke.
+    Network(String),
+    /// The upstream server answered with a non-success status code.
+    Status(u16),
+    /// The upstream response was not a valid event list.
+    Deserialize(String),
+    /// The requested semester has no valid date range.
+    InvalidSemester,
+    /// The service is misconfigured.
+    Config(String),
+    /// The query parameters, given or stored for a subscription, are invalid.
+    InvalidQuery(String),
+    /// No subscription is stored under the token.
+    UnknownSubscription,
+    /// No further subscriptions can be stored.
+    SubscriptionsUnavailable(String),
+}
+
+impl Error {
+    fn code(&self) -> &'static str {
+        match self {
+            Error::Network(_) => "upstream_unreachable",
+            Error::Status(_) => "upstream_status",
+            Error::Deserialize(_) => "upstream_invalid_response",
+            Error::InvalidSemester => "invalid_semester",
+            Error::Config(_) => "invalid_configuration",
+            Error::InvalidQuery(_) => "invalid_query",
+            Error::UnknownSubscription => "unknown_subscription",
+            Error::SubscriptionsUnavailable(_) => "subscriptions_unavailable",
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            Error::Network(_) | Error::SubscriptionsUnavailable(_) => Status::ServiceUnavailable,
+            Error::Status(_) | Error::Deserialize(_) => Status::BadGateway,
+            Error::InvalidSemester => Status::BadRequest,
+            Error::Config(_) => Status::InternalServerError,
+            Error::InvalidQuery(_) => Status::UnprocessableEntity,
+            Error::UnknownSubscription => Status::NotFound,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(error) => write!(f, "schedule server is unreachable: {error}"),
+            Error::Status(status) => write!(f, "schedule server responded with status {status}"),
+            Error::Deserialize(error) => {
+                write!(f, "schedule server sent an invalid response: {error}")
+            }
+            Error::InvalidSemester => write!(f, "semester has no valid date range"),
+            Error::Config(error) => write!(f, "invalid configuration: {error}"),
+            Error::InvalidQuery(errors) => write!(f, "invalid calendar query: {errors}"),
+            Error::UnknownSubscription => write!(f, "no subscription with this token"),
+            Error::SubscriptionsUnavailable(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if let Some(status) = error.status() {
+            Error::Status(status.as_u16())
+        } else if error.is_decode() {
+            Error::Deserialize(error.to_string())
+        } else {
+            Error::Network(error.to_string())
+        }
+    }
+}
+
+impl From<form::Errors<'_>> for Error {
+    fn from(errors: form::Errors<'_>) -> Self {
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|error| match &error.name {
+                Some(name) => format!("{name}: {error}"),
+                None => error.to_string(),
+            })
+            .collect();
+        Error::InvalidQuery(errors.join(", "))
+    }
+}
+
+/// Answers requests Rocket turned down before they reached a route in the format of [`Error`],
+/// e.g. unknown paths or missing and malformed query parameters.
+#[catch(default)]
+pub fn catch_default(status: Status, _: &Request) -> (Status, Json<ErrorBody>) {
+    let (error, message) = match status.code {
+        404 => ("not_found", "no such resource".to_string()),
+        422 => (
+            "invalid_query",
+            "missing or malformed query parameters".to_string(),
+        ),
+        400..=499 => ("bad_request", status.to_string()),
+        _ => ("internal_error", status.to_string()),
+    };
+    let body = ErrorBody {
+        error,
+        message,
+        upstream_status: None,
+    };
+    (status, Json(body))
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody {
+    error: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_status: Option<u16>,
+}
+
+impl<'r> Responder<'r, 'static> for Error {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        warn_!("{self}");
+        let body = ErrorBody {
+            error: self.code(),
+            message: self.to_string(),
+            upstream_status: match self {
+                Error::Status(status) => Some(status),
+                _ => None,
+            },
+        };
+        (self.status(), Json(body)).respond_to(request)
+    }
+}
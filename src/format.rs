@@ -0,0 +1,380 @@
+use rocket::{
+    http::{Accept, ContentType},
+    serde::json::{serde_json::Map, Value},
+};
+
+/// Representation of a calendar, picked with the `format` query parameter or the Accept header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum Format {
+    #[field(value = "ics")]
+    ICalendar,
+    /// RFC 7265
+    #[field(value = "jcal")]
+    JCal,
+    /// RFC 6321
+    #[field(value = "xcal")]
+    XCal,
+}
+
+const XCAL_NAMESPACE: &str = "urn:ietf:params:xml:ns:icalendar-2.0";
+
+impl Format {
+    /// The supported format the client prefers, iCalendar if it accepts none of them.
+    pub fn negotiate(accept: Option<&Accept>) -> Self {
+        let mut media_types: Vec<_> = accept.into_iter().flat_map(Accept::iter).collect();
+        media_types.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+        media_types
+            .into_iter()
+            .find_map(|media_type| {
+                [Self::ICalendar, Self::JCal, Self::XCal]
+                    .into_iter()
+                    .find(|format| format.content_type().media_type() == media_type.media_type())
+            })
+            .unwrap_or(Self::ICalendar)
+    }
+
+    pub fn content_type(self) -> ContentType {
+        match self {
+            Self::ICalendar => ContentType::Calendar,
+            Self::JCal => ContentType::new("application", "calendar+json"),
+            Self::XCal => ContentType::new("application", "calendar+xml"),
+        }
+    }
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::ICalendar => "calendar.ics",
+            Self::JCal => "calendar.json",
+            Self::XCal => "calendar.xml",
+        }
+    }
+
+    /// Converts the iCalendar text into this format, failing if it is malformed.
+    pub fn render(self, ics: String) -> Result<String, String> {
+        Ok(match self {
+            Self::ICalendar => ics,
+            Self::JCal => Component::parse(&ics)?.to_jcal().to_string(),
+            Self::XCal => {
+                let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+                xml.push_str(&format!(r#"<icalendar xmlns="{XCAL_NAMESPACE}">"#));
+                Component::parse(&ics)?.write_xcal(&mut xml);
+                xml.push_str("</icalendar>");
+                xml
+            }
+        })
+    }
+}
+
+/// A component of an iCalendar stream, parsed back from the text written by the ics crate.
+#[derive(Debug, Default)]
+struct Component {
+    name: String,
+    properties: Vec<Property>,
+    components: Vec<Component>,
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    parameters: Vec<(String, String)>,
+    value: String,
+}
+
+impl Component {
+    /// Parses `ics`, which has to consist of exactly one component.
+    fn parse(ics: &str) -> Result<Self, String> {
+        let mut stack: Vec<Component> = Vec::new();
+        let mut parsed = None;
+        for line in unfold(ics) {
+            if parsed.is_some() {
+                return Err(format!("content after the end of the calendar: {line}"));
+            }
+            let property = Property::parse(&line)?;
+            match property.name.as_str() {
+                "begin" => stack.push(Component {
+                    name: property.value.to_lowercase(),
+                    ..Component::default()
+                }),
+                "end" => {
+                    let component = stack
+                        .pop()
+                        .filter(|component| component.name == property.value.to_lowercase())
+                        .ok_or_else(|| format!("unexpected {line}"))?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.components.push(component),
+                        None => parsed = Some(component),
+                    }
+                }
+                _ => match stack.last_mut() {
+                    Some(component) => component.properties.push(property),
+                    None => return Err(format!("property outside of a component: {line}")),
+                },
+            }
+        }
+        parsed.ok_or_else(|| "missing or unterminated component".to_string())
+    }
+
+    fn to_jcal(&self) -> Value {
+        Value::Array(vec![
+            Value::String(self.name.clone()),
+            self.properties.iter().map(Property::to_jcal).collect(),
+            self.components.iter().map(Component::to_jcal).collect(),
+        ])
+    }
+
+    fn write_xcal(&self, xml: &mut String) {
+        xml.push_str(&format!("<{}>", self.name));
+        if !self.properties.is_empty() {
+            xml.push_str("<properties>");
+            self.properties
+                .iter()
+                .for_each(|property| property.write_xcal(xml));
+            xml.push_str("</properties>");
+        }
+        if !self.components.is_empty() {
+            xml.push_str("<components>");
+            self.components
+                .iter()
+                .for_each(|component| component.write_xcal(xml));
+            xml.push_str("</components>");
+        }
+        xml.push_str(&format!("</{}>", self.name));
+    }
+}
+
+impl Property {
+    fn parse(line: &str) -> Result<Self, String> {
+        let (head, value) =
+            split_unquoted(line, ':').ok_or_else(|| format!("property without value: {line}"))?;
+        let mut head = split_all_unquoted(head, ';').into_iter();
+        let name = head.next().unwrap_or_default().to_lowercase();
+        if name.is_empty() {
+            return Err(format!("property without name: {line}"));
+        }
+        let parameters = head
+            .map(|parameter| {
+                let (name, value) = parameter
+                    .split_once('=')
+                    .ok_or_else(|| format!("parameter without value: {line}"))?;
+                Ok((name.to_lowercase(), value.trim_matches('"').to_string()))
+            })
+            .collect::<Result<_, String>>()?;
+        let property = Self {
+            name,
+            parameters,
+            value: value.to_string(),
+        };
+        if property.value_type() == "cal-address" && !is_uri(&property.value) {
+            return Err(format!("cal-address is no URI: {line}"));
+        }
+        Ok(property)
+    }
+
+    /// The value type, either given by the VALUE parameter or the default of the property.
+    fn value_type(&self) -> String {
+        if let Some((_, value)) = self.parameters.iter().find(|(name, _)| name == "value") {
+            return value.to_lowercase();
+        }
+        match self.name.as_str() {
+            "dtstamp" | "dtstart" | "dtend" | "last-modified" | "created" | "recurrence-id"
+            | "exdate" | "rdate" => "date-time",
+            "sequence" => "integer",
+            "tzoffsetfrom" | "tzoffsetto" => "utc-offset",
+            "duration" | "trigger" => "duration",
+            "organizer" | "attendee" => "cal-address",
+            "rrule" => "recur",
+            name if name.starts_with("x-") => "unknown",
+            _ => "text",
+        }
+        .to_string()
+    }
+
+    /// The values in the format shared by jCal and xCal.
+    fn values(&self, value_type: &str) -> Vec<String> {
+        match value_type {
+            "date-time" | "date" | "utc-offset" => self
+                .value
+                .split(',')
+                .map(|value| format_value(value_type, value))
+                .collect(),
+            "text" => split_text(&self.value),
+            "recur" => Vec::new(),
+            _ => vec![self.value.clone()],
+        }
+    }
+
+    /// The rule parts of a recur value, with dates in the extended format.
+    fn recur_parts(&self) -> Vec<(String, String)> {
+        self.value
+            .split(';')
+            .filter_map(|part| part.split_once('='))
+            .map(|(name, value)| {
+                let name = name.to_lowercase();
+                let value = match name.as_str() {
+                    "until" if value.len() == 8 => format_value("date", value),
+                    "until" => format_value("date-time", value),
+                    _ => value.to_string(),
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    fn visible_parameters(&self) -> impl Iterator<Item = &(String, String)> {
+        self.parameters.iter().filter(|(name, _)| name != "value")
+    }
+
+    fn to_jcal(&self) -> Value {
+        let value_type = self.value_type();
+        let parameters: Map<String, Value> = self
+            .visible_parameters()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect();
+        let mut property = vec![
+            Value::String(self.name.clone()),
+            Value::Object(parameters),
+            Value::String(value_type.clone()),
+        ];
+        if value_type == "recur" {
+            let parts: Map<String, Value> = self
+                .recur_parts()
+                .into_iter()
+                .map(|(name, value)| (name, Value::String(value)))
+                .collect();
+            property.push(Value::Object(parts));
+            return Value::Array(property);
+        }
+        property.extend(self.values(&value_type).into_iter().map(|value| {
+            match value_type.as_str() {
+                "integer" => value
+                    .parse::<i64>()
+                    .map_or(Value::String(value), Value::from),
+                _ => Value::String(value),
+            }
+        }));
+        Value::Array(property)
+    }
+
+    fn write_xcal(&self, xml: &mut String) {
+        let value_type = self.value_type();
+        xml.push_str(&format!("<{}>", self.name));
+        let mut parameters = self.visible_parameters().peekable();
+        if parameters.peek().is_some() {
+            xml.push_str("<parameters>");
+            for (name, value) in parameters {
+                xml.push_str(&format!(
+                    "<{name}><text>{}</text></{name}>",
+                    escape_xml(value)
+                ));
+            }
+            xml.push_str("</parameters>");
+        }
+        if value_type == "recur" {
+            xml.push_str("<recur>");
+            for (name, value) in self.recur_parts() {
+                xml.push_str(&format!("<{name}>{}</{name}>", escape_xml(&value)));
+            }
+            xml.push_str("</recur>");
+        }
+        for value in self.values(&value_type) {
+            xml.push_str(&format!(
+                "<{value_type}>{}</{value_type}>",
+                escape_xml(&value)
+            ));
+        }
+        xml.push_str(&format!("</{}>", self.name));
+    }
+}
+
+/// Joins folded lines, see RFC 5545 section 3.1.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.split("\r\n").filter(|line| !line.is_empty()) {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits at the first `separator` that is not within double quotes.
+fn split_unquoted(text: &str, separator: char) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => return Some((&text[..index], &text[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_all_unquoted(mut text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    while let Some((part, rest)) = split_unquoted(text, separator) {
+        parts.push(part);
+        text = rest;
+    }
+    parts.push(text);
+    parts
+}
+
+/// Whether `value` starts with a URI scheme, e.g. `MAILTO:`.
+fn is_uri(value: &str) -> bool {
+    value.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Unescapes a text value, splitting it at unescaped commas.
+fn split_text(value: &str) -> Vec<String> {
+    let mut values = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let current = values.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => current.push('\n'),
+                Some(escaped) => current.push(escaped),
+                None => {}
+            },
+            ',' => values.push(String::new()),
+            c => current.push(c),
+        }
+    }
+    values
+}
+
+/// Converts a basic format value of RFC 5545 to the extended format of RFC 7265 and 6321.
+fn format_value(value_type: &str, value: &str) -> String {
+    let value = value.trim();
+    let part = |range: std::ops::Range<usize>| value.get(range).unwrap_or_default();
+    match value_type {
+        "date" => format!("{}-{}-{}", part(0..4), part(4..6), part(6..8)),
+        "date-time" => format!(
+            "{}-{}-{}T{}:{}:{}{}",
+            part(0..4),
+            part(4..6),
+            part(6..8),
+            part(9..11),
+            part(11..13),
+            part(13..15),
+            part(15..16)
+        ),
+        "utc-offset" => format!("{}:{}", part(0..3), part(3..5)),
+        _ => value.to_string(),
+    }
+}
+
+/// Escapes text for XML and HTML.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,75 +1,428 @@
-use std::{collections::HashSet, io::Cursor};
+use std::{collections::HashSet, fmt, io::Cursor, ops::RangeInclusive};
 
-use cached::proc_macro::cached;
-use chrono::{NaiveDate, NaiveDateTime, TimeZone};
-use chrono_tz::Europe::Berlin;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use chrono_tz::{Europe::Berlin, Tz};
 use ics::{
+    components::Property,
     escape_text,
+    parameters::{TzIDParam, Value, CN},
     properties::{
-        Categories, Description, DtEnd, DtStart, Duration, Location as IcsLocation, Organizer,
-        Summary,
+        Categories, Description, DtEnd, DtStart, LastModified, Location as IcsLocation,
+        ExDate, Organizer, RRule, RecurrenceID, Sequence, Status, Summary, Trigger,
     },
-    Event as IcsEvent, ICalendar,
+    Alarm, Event as IcsEvent, ICalendar,
 };
+use cache::{Cached, FeedCache};
+use config::{AcademicYear, ScheduleConfig, SemesterWindows};
+use csv::{Csv, CsvOptions};
+use error::Error;
+use format::Format;
+use timezone::Resolution;
+use recurrence::Item;
+use revision::{Revision, Revisions};
+use selection::{HolidayScope, ScopedSelector, Selection, Selector};
+use subscription::Subscriptions;
+use uid::Uids;
 use reqwest::{header::CONTENT_DISPOSITION, Client, Url};
 use rocket::{
-    http::{ContentType, Header},
-    response::Responder,
-    serde::json::Json,
-    Response,
+    fairing::AdHoc,
+    form::{self, DataField, Form, FromFormField, ValueField},
+    http::{Header, RawStr},
+    response::{content::RawHtml, status::Created, Responder},
+    serde::json::{self, Json},
+    Response, State,
 };
-use serde::{Deserialize, Deserializer, Serialize};
-use time::{OffsetDateTime, format_description::{FormatItem, self}};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate rocket;
 
+mod cache;
+mod config;
+mod csv;
+mod error;
+mod format;
+mod recurrence;
+mod revision;
+mod selection;
+mod store;
+mod subscription;
+#[cfg(test)]
+mod tests;
+mod timetable;
+mod timezone;
+mod uid;
+
 const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
-const ACADEMIC_YEAR_NAMES: [&str; 4] = ["1. Lehrjahr", "2. Lehrjahr", "3. Lehrjahr", "Wahlpflicht"];
+const ALL_DAY_DATE_FORMAT: &str = "%Y%m%d";
+const EXAM_KEYWORDS: [&str; 3] = ["klausur", "prüfung", "exam"];
+const CANCELLED_DESCRIPTION: &str = "Dieser Termin entfällt, er wurde aus dem Stundenplan entfernt.";
+const DST_ADJUSTED_DESCRIPTION: &str =
+    "Die Uhrzeit dieses Termins fällt in die Zeitumstellung und wurde angepasst.";
+/// Marks events whose times fell into a DST transition, see [`timezone::Resolution`].
+const DST_ADJUSTED_PROPERTY: &str = "X-MATSE-DST-ADJUSTED";
+
+/// Years of the semesters, cohorts and date ranges that can be requested.
+const SUPPORTED_YEARS: RangeInclusive<i32> = 2000..=2100;
+/// Longest `from`/`to` range, about a whole apprenticeship.
+const MAX_RANGE_DAYS: i64 = 3 * 366;
 
 lazy_static! {
     static ref REQWEST_CLIENT: Client = Client::new();
-    static ref MATSE_SCHEDULE_URL: Url =
-        Url::parse("https://www.matse.itc.rwth-aachen.de/stundenplan/web/eventFeed/").unwrap();
-    static ref DATE_FORMAT_TIME: Vec<FormatItem<'static>> = format_description::parse("[year][month][day]T[hour][minute][second]Z").unwrap();
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, FromForm)]
+#[derive(Hash, PartialEq, Eq, Clone, FromForm, Serialize, Deserialize)]
 struct Semester {
+    #[field(validate = supported_year())]
     year: i32,
     winter_semester: bool,
 }
 
-impl Semester {
-    fn get_start_date(&self) -> Option<NaiveDate> {
+/// Rejects years the upstream has no schedule for, e.g. typos like `year=20233`.
+fn supported_year<'v>(year: &i32) -> form::Result<'v, ()> {
+    if SUPPORTED_YEARS.contains(year) {
+        Ok(())
+    } else {
+        Err(form::Error::validation(format!(
+            "year {year} is not between {} and {}",
+            SUPPORTED_YEARS.start(),
+            SUPPORTED_YEARS.end()
+        ))
+        .into())
+    }
+}
+
+impl fmt::Display for Semester {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.winter_semester {
-            NaiveDate::from_ymd_opt(self.year, 9, 1)
+            write!(f, "Wintersemester {}/{:02}", self.year, (self.year + 1) % 100)
         } else {
-            NaiveDate::from_ymd_opt(self.year, 3, 1)
+            write!(f, "Sommersemester {}", self.year)
         }
     }
+}
 
-    fn get_end_date(&self) -> Option<NaiveDate> {
+impl Semester {
+    fn get_start_date(&self, windows: &SemesterWindows) -> Option<NaiveDate> {
+        windows
+            .window(self.year, self.winter_semester)
+            .map(|(start, _)| start)
+    }
+
+    fn get_end_date(&self, windows: &SemesterWindows) -> Option<NaiveDate> {
+        windows
+            .window(self.year, self.winter_semester)
+            .map(|(_, end)| end)
+    }
+
+    /// The semester that started last on or before `date`.
+    fn current(date: NaiveDate, windows: &SemesterWindows) -> Self {
+        let year = date.year();
+        [(year, true), (year, false), (year - 1, true)]
+            .into_iter()
+            .map(|(year, winter_semester)| Self {
+                year,
+                winter_semester,
+            })
+            .find(|semester| {
+                semester
+                    .get_start_date(windows)
+                    .is_some_and(|start| start <= date)
+            })
+            .unwrap_or(Self {
+                year: year - 1,
+                winter_semester: true,
+            })
+    }
+
+    fn next(&self) -> Self {
         if self.winter_semester {
-            NaiveDate::from_ymd_opt(self.year + 1, 3, 15)
+            Self {
+                year: self.year + 1,
+                winter_semester: false,
+            }
         } else {
-            NaiveDate::from_ymd_opt(self.year, 9, 15)
+            Self {
+                year: self.year,
+                winter_semester: true,
+            }
+        }
+    }
+}
+
+/// The query parameters picking the semesters of a request, see [`SemesterQuery::resolve`].
+struct SemesterQuery {
+    year: Option<i32>,
+    winter_semester: bool,
+    current: bool,
+    include_next: bool,
+    cohort: Option<i32>,
+    from: Option<QueryDate>,
+    to: Option<QueryDate>,
+}
+
+/// A `YYYY-MM-DD` query parameter.
+#[derive(Clone, Copy)]
+struct QueryDate(NaiveDate);
+
+impl<'v> FromFormField<'v> for QueryDate {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        NaiveDate::parse_from_str(field.value, "%Y-%m-%d")
+            .map(Self)
+            .map_err(|error| form::Error::validation(error.to_string()).into())
+    }
+}
+
+/// An optional query parameter. Unlike with `Option`, which Rocket turns into `None` on any
+/// error, a given but malformed value is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Optional<T>(Option<T>);
+
+impl<T> Default for Optional<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<'v, T: FromFormField<'v>> FromFormField<'v> for Optional<T> {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        T::from_value(field).map(|value| Self(Some(value)))
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+impl SemesterQuery {
+    /// The range between `from` and `to`, the cohort starting in `cohort`, otherwise either the
+    /// semester given by `year` and `winter_semester` or, if `current` is set, the current one by
+    /// date, so subscriptions keep working across semesters.
+    ///
+    /// Only one of them can be given, and `include_next` only with a single semester.
+    fn resolve(self, windows: &SemesterWindows) -> Result<Semesters, Error> {
+        let invalid = |message: &str| Err(Error::InvalidQuery(message.into()));
+        let years = [self.from, self.to].map(|date| date.map(|QueryDate(date)| date.year()));
+        for year in [self.year, self.cohort].into_iter().chain(years).flatten() {
+            supported_year(&year)?;
+        }
+        let range = self.from.is_some() || self.to.is_some();
+        let selectors = [
+            self.year.is_some(),
+            self.current,
+            self.cohort.is_some(),
+            range,
+        ];
+        if selectors.into_iter().filter(|given| *given).count() > 1 {
+            return invalid("only one of year, current=true, cohort or from and to can be given");
         }
+        if self.include_next && (self.cohort.is_some() || range) {
+            return invalid("include_next is only supported with year or current=true");
+        }
+        let semester = match self {
+            Self {
+                from: Some(QueryDate(from)),
+                to: Some(QueryDate(to)),
+                ..
+            } => {
+                if from > to {
+                    return invalid("from must not be after to");
+                }
+                if to - from > TimeDelta::days(MAX_RANGE_DAYS) {
+                    return Err(Error::InvalidQuery(format!(
+                        "from and to must be at most {MAX_RANGE_DAYS} days apart"
+                    )));
+                }
+                return Ok(Semesters::Range { from, to });
+            }
+            Self { from: Some(_), .. } | Self { to: Some(_), .. } => {
+                return invalid("from and to are only supported together");
+            }
+            Self {
+                cohort: Some(start_year),
+                ..
+            } => return Ok(Semesters::Cohort { start_year }),
+            Self { current: true, .. } => Semester::current(today(), windows),
+            Self {
+                year: Some(year),
+                winter_semester,
+                ..
+            } => Semester {
+                year,
+                winter_semester,
+            },
+            _ => return invalid("either year, current=true, cohort or from and to are required"),
+        };
+        Ok(Semesters::Single {
+            semester,
+            include_next: self.include_next,
+        })
     }
 }
 
-#[derive(Clone, Deserialize)]
+/// The semesters a request covers.
+enum Semesters {
+    Single {
+        semester: Semester,
+        /// Also the following semester, once the upstream publishes its schedule.
+        include_next: bool,
+    },
+    /// The whole apprenticeship of the cohort starting in the winter semester of `start_year`,
+    /// following the academic year feeds by their `cohort_year`.
+    Cohort { start_year: i32 },
+    /// The events between the two days, taken from every semester overlapping them.
+    Range { from: NaiveDate, to: NaiveDate },
+}
+
+/// A semester of a request and the academic year feeds its events are fetched from.
+struct SemesterFeeds<'c> {
+    semester: Semester,
+    academic_years: Vec<&'c AcademicYear>,
+    /// Only the events starting within this range, the end excluded, are kept.
+    keep: Option<(NaiveDate, NaiveDate)>,
+    /// Failing to fetch the semester only adds a warning, its schedule might not be published.
+    optional: bool,
+}
+
+impl Semesters {
+    fn feeds<'c>(&self, config: &'c ScheduleConfig) -> Result<Vec<SemesterFeeds<'c>>, Error> {
+        let windows = &config.semester_windows;
+        let unpublished = |semester: &Semester| {
+            semester
+                .get_start_date(windows)
+                .is_none_or(|start| start > today())
+        };
+        match self {
+            Self::Single {
+                semester,
+                include_next,
+            } => {
+                let academic_years: Vec<_> = config.academic_years.iter().collect();
+                let mut feeds = vec![SemesterFeeds {
+                    semester: semester.clone(),
+                    academic_years: academic_years.clone(),
+                    keep: None,
+                    optional: false,
+                }];
+                if *include_next {
+                    feeds.push(SemesterFeeds {
+                        semester: semester.next(),
+                        academic_years,
+                        keep: None,
+                        optional: true,
+                    });
+                }
+                Ok(feeds)
+            }
+            Self::Cohort { start_year } => {
+                let years = config
+                    .academic_years
+                    .iter()
+                    .filter_map(|academic_year| academic_year.cohort_year)
+                    .max()
+                    .ok_or_else(|| Error::Config("no academic year has a cohort_year".into()))?;
+                let mut semester = Semester {
+                    year: *start_year,
+                    winter_semester: true,
+                };
+                let mut feeds = Vec::new();
+                for index in 0..u16::from(years) * 2 {
+                    // consecutive semesters overlap, each keeps the events until the next starts
+                    let keep = semester
+                        .get_start_date(windows)
+                        .zip(semester.next().get_start_date(windows))
+                        .ok_or(Error::InvalidSemester)?;
+                    feeds.push(SemesterFeeds {
+                        semester: semester.clone(),
+                        academic_years: config
+                            .academic_years
+                            .iter()
+                            .filter(|academic_year| {
+                                academic_year.cohort_year.is_none_or(|cohort_year| {
+                                    u16::from(cohort_year) == index / 2 + 1
+                                })
+                            })
+                            .collect(),
+                        keep: Some(keep),
+                        optional: unpublished(&semester),
+                    });
+                    semester = semester.next();
+                }
+                Ok(feeds)
+            }
+            Self::Range { from, to } => {
+                let mut semester = Semester {
+                    year: from.year() - 1,
+                    winter_semester: true,
+                };
+                let mut feeds = Vec::new();
+                loop {
+                    let (start, end) = semester
+                        .get_start_date(windows)
+                        .zip(semester.get_end_date(windows))
+                        .ok_or(Error::InvalidSemester)?;
+                    if start > *to {
+                        break;
+                    }
+                    if end >= *from {
+                        feeds.push(SemesterFeeds {
+                            semester: semester.clone(),
+                            academic_years: config.academic_years.iter().collect(),
+                            keep: Some((*from, *to + TimeDelta::days(1))),
+                            optional: unpublished(&semester),
+                        });
+                    }
+                    semester = semester.next();
+                }
+                Ok(feeds)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Semesters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Single {
+                semester,
+                include_next: true,
+            } => write!(f, "{semester} und {}", semester.next()),
+            Self::Single { semester, .. } => semester.fmt(f),
+            Self::Cohort { start_year } => write!(f, "Jahrgang {start_year}"),
+            Self::Range { from, to } => write!(
+                f,
+                "{} bis {}",
+                from.format("%d.%m.%Y"),
+                to.format("%d.%m.%Y")
+            ),
+        }
+    }
+}
+
+/// The date in Germany.
+fn today() -> NaiveDate {
+    Utc::now().with_timezone(&Berlin).date_naive()
+}
+
+/// An event of the upstream feed, normalized while parsing.
+///
+/// The serialized form is the public schema of `/events`, only extend it.
+#[derive(Clone, Deserialize, Serialize)]
 struct Event {
+    #[serde(default, deserialize_with = "string_from_any_option")]
+    id: Option<String>,
+    /// Id of the academic year feed the event was fetched from.
+    #[serde(skip_deserializing, rename = "academicYear")]
+    academic_year: String,
     #[serde(deserialize_with = "strip_bang")]
     name: String,
-    #[serde(deserialize_with = "naive_from_berlin")]
-    start: NaiveDateTime,
-    #[serde(deserialize_with = "naive_from_berlin")]
-    end: NaiveDateTime,
+    #[serde(flatten)]
+    period: Period,
     location: Location,
     lecturer: Lecturer,
+    #[serde(serialize_with = "information_text")]
     information: Option<String>,
     #[serde(deserialize_with = "bool_from_str_option", rename = "isHoliday")]
     is_holiday: bool,
@@ -79,19 +432,124 @@ struct Event {
     is_all_day: bool,
     #[serde(deserialize_with = "bool_from_str_option", rename = "isLecture")]
     is_lecture: bool,
+    /// Set for events that disappeared from the upstream feed.
+    #[serde(skip_deserializing, rename = "isCancelled")]
+    is_cancelled: bool,
 }
 
 impl Event {
     fn get_start_date(&self) -> String {
-        self.start.format(DATE_FORMAT).to_string()
+        self.period.start.naive_utc().format(DATE_FORMAT).to_string()
     }
 
     fn get_end_date(&self) -> String {
-        self.end.format(DATE_FORMAT).to_string()
+        self.period.end.naive_utc().format(DATE_FORMAT).to_string()
     }
+
+    /// The upstream has no flag for exams, so they are recognized by their name.
+    fn is_exam(&self) -> bool {
+        let name = self.name.to_lowercase();
+        EXAM_KEYWORDS.iter().any(|keyword| name.contains(keyword))
+    }
+
+    fn event_type(&self) -> EventType {
+        if self.is_holiday {
+            EventType::Holiday
+        } else if self.is_exam() {
+            EventType::Exam
+        } else if self.is_lecture {
+            EventType::Lecture
+        } else if self.is_exercise {
+            EventType::Exercise
+        } else {
+            EventType::Other
+        }
+    }
+
+    /// Identifies the event across refreshes of its feed.
+    fn identity(&self) -> String {
+        match &self.id {
+            Some(id) => id.clone(),
+            None => format!("{}-{}", self.get_start_date(), self.name),
+        }
+    }
+}
+
+/// Kind of an event, derived from the upstream flags and the name for exams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+enum EventType {
+    Lecture,
+    Exercise,
+    Exam,
+    Holiday,
+    Other,
 }
 
-#[derive(Clone, Deserialize)]
+impl EventType {
+    const ALL: [EventType; 5] = [
+        EventType::Lecture,
+        EventType::Exercise,
+        EventType::Exam,
+        EventType::Holiday,
+        EventType::Other,
+    ];
+}
+
+/// Start and end of an event, sent by the upstream as Europe/Berlin wall clock times.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(from = "UpstreamPeriod")]
+struct Period {
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    /// Set if start or end fell into a DST transition and had to be adjusted.
+    #[serde(rename = "dstAdjusted")]
+    dst_adjusted: bool,
+}
+
+#[derive(Deserialize)]
+struct UpstreamPeriod {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+impl Period {
+    /// First day and exclusive end day of an all-day event in Europe/Berlin.
+    ///
+    /// The upstream sends the last day either ending at 23:59:59 or, like iCalendar, as the
+    /// following midnight, so only the latter is treated as exclusive.
+    fn dates(&self) -> (NaiveDate, NaiveDate) {
+        let first = self.start.date_naive();
+        let last = self.end.date_naive();
+        let end = if self.end > self.start && self.end.time() == NaiveTime::MIN {
+            last
+        } else {
+            last + TimeDelta::days(1)
+        };
+        (first, end.max(first + TimeDelta::days(1)))
+    }
+}
+
+impl From<UpstreamPeriod> for Period {
+    /// Resolves both times with [`timezone::from_local`]. If that would move the end before the
+    /// start, the wall clock duration is kept instead.
+    fn from(period: UpstreamPeriod) -> Self {
+        let (start, start_resolution) = timezone::from_local(Berlin, &period.start);
+        let (end, end_resolution) = timezone::from_local(Berlin, &period.end);
+        let end = if end < start {
+            start + (period.end - period.start)
+        } else {
+            end
+        };
+        Self {
+            start,
+            end,
+            dst_adjusted: start_resolution != Resolution::Exact
+                || end_resolution != Resolution::Exact,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 struct Location {
     name: Option<String>,
     street: Option<String>,
@@ -99,8 +557,8 @@ struct Location {
     desc: Option<String>,
 }
 
-impl ToString for Location {
-    fn to_string(&self) -> String {
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let empty = String::new();
         let name = self
             .name
@@ -112,12 +570,13 @@ impl ToString for Location {
             .as_ref()
             .map(|street| format!("{street} {}\n", self.nr.as_ref().unwrap_or(&empty)))
             .unwrap_or_default();
-        format!(
-            "{name}{address}{}",
-            self.desc.as_ref().unwrap_or(&empty)
+        f.write_str(
+            format!(
+                "{name}{address}{}",
+                self.desc.as_ref().unwrap_or(&empty)
+            )
+            .trim(),
         )
-        .trim()
-        .into()
     }
 }
 
@@ -127,29 +586,56 @@ impl Location {
     }
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 struct Lecturer {
     name: Option<String>,
     mail: Option<String>,
 }
 
-impl ToString for Lecturer {
-    fn to_string(&self) -> String {
+impl fmt::Display for Lecturer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match (self.name.as_ref(), self.mail.as_ref()) {
-            (Some(name), Some(mail)) => format!("CN={name}:MAILTO:{mail}"),
-            (Some(name), None) => format!("CN={name}"),
-            (_, Some(mail)) => format!(":MAILTO:{mail}"),
-            _ => String::new(),
+            (Some(name), Some(mail)) => write!(f, "CN={name}:MAILTO:{mail}"),
+            (Some(name), None) => write!(f, "CN={name}"),
+            (_, Some(mail)) => write!(f, ":MAILTO:{mail}"),
+            _ => Ok(()),
         }
     }
 }
 
 impl Lecturer {
-    fn contains_information(&self) -> bool {
-        self.name.is_some() || self.mail.is_some()
+    /// The lecturer as ORGANIZER with the name as common name. Lecturers without a mail address
+    /// are left out, as the organizer has to be an address.
+    fn organizer(&self) -> Option<Organizer<'static>> {
+        let mail = self.mail.as_ref()?;
+        let mut organizer = Organizer::new(format!("MAILTO:{mail}"));
+        if let Some(name) = &self.name {
+            // quoted, as names may contain `:`, `;` or `,`, without the quotes they cannot contain
+            organizer.add(CN::new(format!("\"{}\"", name.replace('"', ""))));
+        }
+        Some(organizer)
     }
 }
 
+/// The upstream separates lines with `<br />` and sends empty strings instead of null.
+fn plain_text(information: &Option<String>) -> Option<String> {
+    information
+        .as_ref()
+        .map(|information| information.replace("<br />", "\n"))
+        .filter(|information| !information.is_empty())
+}
+
+fn information_text<S>(information: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    plain_text(information).serialize(serializer)
+}
+
+fn format_berlin(local: &NaiveDateTime) -> String {
+    timezone::format_local(&timezone::from_local(Berlin, local).0)
+}
+
 fn strip_bang<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>
@@ -157,59 +643,122 @@ where
     Deserialize::deserialize(deserializer).map(|val: String| val.trim_start_matches("(!) ").to_string())
 }
 
-fn bool_from_str_option<'de, D>(deserializer: D) -> Result<bool, D::Error>
+fn string_from_any_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    match Option::deserialize(deserializer)? {
-        Some("0") | Some("") | None => Ok(false),
-        Some(_) => Ok(true),
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(i64),
     }
+
+    Ok(match Option::deserialize(deserializer)? {
+        Some(StringOrNumber::String(string)) if !string.is_empty() => Some(string),
+        Some(StringOrNumber::Number(number)) => Some(number.to_string()),
+        _ => None,
+    })
 }
 
-fn naive_from_berlin<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+fn bool_from_str_option<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let date = NaiveDateTime::deserialize(deserializer)?;
-    let date = Berlin.from_local_datetime(&date).unwrap();
-    Ok(date.naive_utc())
+    match Option::deserialize(deserializer)? {
+        Some("0") | Some("") | None => Ok(false),
+        Some(_) => Ok(true),
+    }
 }
 
-impl<'a> From<Event> for IcsEvent<'a> {
-    fn from(event: Event) -> Self {
-        let mut ics_event = IcsEvent::new(
-            format!(
-                "{}-{}@matse.morbatex.com",
-                event.get_start_date(),
-                event.name.to_lowercase().replace(' ', "_")
+impl Event {
+    /// Hash of everything that ends up in the VEVENT, changes whenever the event does.
+    fn fingerprint(&self) -> u64 {
+        uid::fnv1a(&[
+            &self.name,
+            &self.get_start_date(),
+            &self.get_end_date(),
+            &self.location.to_string(),
+            &self.lecturer.to_string(),
+            self.information.as_deref().unwrap_or_default(),
+            &format!(
+                "{}{}{}{}{}",
+                self.is_holiday,
+                self.is_exercise,
+                self.is_all_day,
+                self.is_lecture,
+                self.is_cancelled
             ),
-            OffsetDateTime::now_utc().format(&DATE_FORMAT_TIME).unwrap().to_string(),
-        );
-        ics_event.push(DtStart::new(event.get_start_date()));
-        if event.is_all_day {
-            ics_event.push(Duration::new("P24H"));
+        ])
+    }
+
+    fn into_ics_event<'a>(
+        self,
+        uid: String,
+        revision: Revision,
+        options: &CalendarOptions,
+    ) -> IcsEvent<'a> {
+        let modified = revision.modified.format(DATE_FORMAT).to_string();
+        let mut ics_event = IcsEvent::new(uid, modified.clone());
+        ics_event.push(LastModified::new(modified));
+        ics_event.push(Sequence::new(revision.sequence.to_string()));
+        if self.is_all_day {
+            let (first_day, end_day) = self.period.dates();
+            let mut start = DtStart::new(first_day.format(ALL_DAY_DATE_FORMAT).to_string());
+            start.add(Value::DATE);
+            ics_event.push(start);
+            let mut end = DtEnd::new(end_day.format(ALL_DAY_DATE_FORMAT).to_string());
+            end.add(Value::DATE);
+            ics_event.push(end);
+        } else if options.uses_local_time() {
+            let mut start = DtStart::new(timezone::format_local(&self.period.start));
+            start.add(TzIDParam::new(Berlin.name()));
+            ics_event.push(start);
+            let mut end = DtEnd::new(timezone::format_local(&self.period.end));
+            end.add(TzIDParam::new(Berlin.name()));
+            ics_event.push(end);
         } else {
-            ics_event.push(DtEnd::new(event.get_end_date()));
+            ics_event.push(DtStart::new(self.get_start_date()));
+            ics_event.push(DtEnd::new(self.get_end_date()));
         }
-        ics_event.push(Summary::new(escape_text(event.name)));
-        if let Some(information) = event.information {
-            let information = information.replace("<br />", "\n");
-            if !information.is_empty() {
-                ics_event.push(Description::new(escape_text(information)));
-            }
+        if let Some(minutes) = options.alarm(&self) {
+            ics_event.add_alarm(Alarm::display(
+                Trigger::new(format!("-PT{minutes}M")),
+                Description::new(escape_text(self.name.clone())),
+            ));
+        }
+        ics_event.push(Summary::new(escape_text(self.name)));
+        let information = plain_text(&self.information).unwrap_or_default();
+        let mut notes = Vec::new();
+        if self.is_cancelled {
+            ics_event.push(Status::cancelled());
+            notes.push(CANCELLED_DESCRIPTION);
+        }
+        if self.period.dst_adjusted {
+            ics_event.push(Property::new(DST_ADJUSTED_PROPERTY, "TRUE"));
+            notes.push(DST_ADJUSTED_DESCRIPTION);
         }
-        if event.location.contains_information() {
-            ics_event.push(IcsLocation::new(escape_text(event.location.to_string())));
+        let description = notes
+            .into_iter()
+            .map(str::to_string)
+            .chain([information])
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !description.is_empty() {
+            ics_event.push(Description::new(escape_text(description)));
+        }
+        if self.location.contains_information() {
+            ics_event.push(IcsLocation::new(escape_text(self.location.to_string())));
         }
-        if event.lecturer.contains_information() {
-            ics_event.push(Organizer::new(escape_text(event.lecturer.to_string())));
+        if let Some(organizer) = self.lecturer.organizer() {
+            ics_event.push(organizer);
         }
-        if event.is_lecture {
+        if self.is_lecture {
             ics_event.push(Categories::new("LECTURE"));
-        } else if event.is_exercise {
+        } else if self.is_exercise {
             ics_event.push(Categories::new("Exercise"));
-        } else if event.is_holiday {
+        } else if self.is_holiday {
             ics_event.push(Categories::new("Holiday"));
         }
         ics_event
@@ -218,123 +767,558 @@ impl<'a> From<Event> for IcsEvent<'a> {
 
 struct Calendar<'a> {
     calendar: ICalendar<'a>,
+    /// Negotiated with the Accept header if not requested explicitly.
+    format: Option<Format>,
 }
 
-impl<'a> ToString for Calendar<'a> {
-    fn to_string(&self) -> String {
-        self.calendar.to_string()
+/// Optional `/calendar` query parameters changing how the events are encoded.
+#[derive(Default, FromForm)]
+struct CalendarOptions {
+    /// Emit Europe/Berlin wall clock times with a VTIMEZONE instead of UTC.
+    #[field(default = false)]
+    local_time: bool,
+    /// Collapse weekly repeating events into RRULE series, implies `local_time` so the series
+    /// keep their wall clock time across DST changes.
+    #[field(default = false)]
+    recurring: bool,
+    format: Optional<Format>,
+    /// Minutes before an event to remind of it, holidays excluded.
+    alarm: Optional<u32>,
+    /// Overrides `alarm` for lectures.
+    alarm_lecture: Optional<u32>,
+    /// Overrides `alarm` for exercises.
+    alarm_exercise: Optional<u32>,
+    /// Overrides `alarm` for exams.
+    alarm_exam: Optional<u32>,
+    /// Minutes before holidays to remind of them.
+    alarm_holiday: Optional<u32>,
+}
+
+impl CalendarOptions {
+    fn uses_local_time(&self) -> bool {
+        self.local_time || self.recurring
+    }
+
+    /// Minutes before `event` to remind of it, if at all.
+    fn alarm(&self, event: &Event) -> Option<u32> {
+        if event.is_cancelled {
+            return None;
+        }
+        match event.event_type() {
+            EventType::Holiday => self.alarm_holiday.0,
+            EventType::Exam => self.alarm_exam.0.or(self.alarm.0),
+            EventType::Lecture => self.alarm_lecture.0.or(self.alarm.0),
+            EventType::Exercise => self.alarm_exercise.0.or(self.alarm.0),
+            EventType::Other => self.alarm.0,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Calendar<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.calendar.fmt(f)
     }
 }
 
-impl<'a> From<Vec<IcsEvent<'a>>> for Calendar<'a> {
-    fn from(events: Vec<IcsEvent<'a>>) -> Self {
+impl<'a> Calendar<'a> {
+    async fn new(
+        config: &ScheduleConfig,
+        revisions: &Revisions,
+        events: Vec<Event>,
+        options: &CalendarOptions,
+    ) -> Self {
         let mut calendar = ICalendar::new("2.0", "-//morbatex/calendar/matse");
-        events
+        if options.uses_local_time() {
+            let from = events.iter().map(|event| event.period.start).min();
+            let to = events.iter().map(|event| event.period.end).max();
+            if let (Some(from), Some(to)) = (from, to) {
+                calendar.add_timezone(timezone::vtimezone(
+                    Berlin,
+                    from.with_timezone(&Utc),
+                    to.with_timezone(&Utc),
+                ));
+            }
+        }
+        let items = if options.recurring {
+            recurrence::collapse(events)
+        } else {
+            events.into_iter().map(Item::Single).collect()
+        };
+        let mut uids = Uids::new(&config.uid_domain);
+        let mut fingerprints = Vec::new();
+        let items = items
             .into_iter()
-            .for_each(|event| calendar.add_event(event));
-        Self { calendar }
+            .map(|item| {
+                let uid = match &item {
+                    Item::Single(event) => {
+                        let uid = uids.next(event);
+                        fingerprints.push((uid.clone(), event.fingerprint()));
+                        uid
+                    }
+                    // series and overrides are tracked separately from the single events with
+                    // the same UID, as other subscribers may not collapse them
+                    Item::Series(series) => {
+                        let uid = uids.next(&series.event);
+                        fingerprints.push((format!("{uid}#series"), series.fingerprint()));
+                        for (start, event) in &series.overrides {
+                            fingerprints.push((format!("{uid}#{start}"), event.fingerprint()));
+                        }
+                        uid
+                    }
+                };
+                (item, uid)
+            })
+            .collect::<Vec<_>>();
+        let retention = config.cancellation_grace_period();
+        let mut revisions = revisions.update(&fingerprints, retention).await.into_iter();
+        for (item, uid) in items {
+            let revision = revisions.next().unwrap();
+            match item {
+                Item::Single(event) => {
+                    calendar.add_event(event.into_ics_event(uid, revision, options));
+                }
+                Item::Series(series) => {
+                    let until = series.until.naive_utc().format(DATE_FORMAT);
+                    let mut ics_event = series.event.into_ics_event(uid.clone(), revision, options);
+                    ics_event.push(RRule::new(format!("FREQ=WEEKLY;UNTIL={until}")));
+                    if !series.excluded.is_empty() {
+                        let excluded: Vec<_> = series.excluded.iter().map(format_berlin).collect();
+                        let mut exdate = ExDate::new(excluded.join(","));
+                        exdate.add(TzIDParam::new(Berlin.name()));
+                        ics_event.push(exdate);
+                    }
+                    calendar.add_event(ics_event);
+                    for (start, event) in series.overrides {
+                        let revision = revisions.next().unwrap();
+                        let mut ics_event = event.into_ics_event(uid.clone(), revision, options);
+                        let mut recurrence_id = RecurrenceID::new(format_berlin(&start));
+                        recurrence_id.add(TzIDParam::new(Berlin.name()));
+                        ics_event.push(recurrence_id);
+                        calendar.add_event(ics_event);
+                    }
+                }
+            }
+        }
+        Self {
+            calendar,
+            format: options.format.0,
+        }
     }
 }
 
 impl<'r, 'a: 'r> Responder<'r, 'a> for Calendar<'a> {
-    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'a> {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'a> {
+        let format = self
+            .format
+            .unwrap_or_else(|| Format::negotiate(request.accept()));
+        let calendar = format.render(self.to_string()).map_err(|error| {
+            error_!("failed to render the calendar as {format:?}: {error}");
+            rocket::http::Status::InternalServerError
+        })?;
         Response::build()
-            .header(ContentType::Calendar)
+            .header(format.content_type())
             .header(Header::new(
                 CONTENT_DISPOSITION.as_str(),
-                " attachment; filename=\"calendar.ics\"",
+                format!(" attachment; filename=\"{}\"", format.file_name()),
             ))
-            .sized_body(None,Cursor::new(self.to_string()))
+            .raw_header("Vary", "Accept")
+            .sized_body(None, Cursor::new(calendar))
             .ok()
     }
 }
 
 #[derive(Serialize)]
 struct EventCategories {
-    name: &'static str,
+    name: String,
     curses: HashSet<String>,
 }
 
-impl From<(&'static str, HashSet<String>)> for EventCategories {
-    fn from((name, curses): (&'static str, HashSet<String>)) -> Self {
+impl From<(String, HashSet<String>)> for EventCategories {
+    fn from((name, curses): (String, HashSet<String>)) -> Self {
         Self { name, curses }
     }
 }
 
-async fn get_selected_events<'a>(semester: Semester, curses: Vec<String>) -> Vec<IcsEvent<'a>> {
-    get_all_events(semester)
-        .await
-        .into_iter()
-        .filter(|event| curses.contains(&event.name))
-        .map(IcsEvent::from)
-        .collect::<Vec<_>>()
+async fn get_selected_events(
+    config: &ScheduleConfig,
+    cache: &FeedCache,
+    semesters: &Semesters,
+    selection: &Selection,
+) -> Result<Cached<Vec<Event>>, Error> {
+    let mut events: Cached<Vec<Event>> = Cached::default();
+    // the date ranges of consecutive semesters overlap, events are only included once
+    let mut known = HashSet::new();
+    let key = |event: &Event| (event.academic_year.clone(), event.identity());
+    for feeds in semesters.feeds(config)? {
+        match get_semester_events(config, cache, &feeds).await {
+            Ok(semester_events) => {
+                let semester_events = semester_events.map(|semester_events| {
+                    semester_events
+                        .into_iter()
+                        .filter(|event| !known.contains(&key(event)))
+                        .collect::<Vec<_>>()
+                });
+                known.extend(semester_events.value.iter().map(key));
+                events.append(semester_events);
+            }
+            Err(error) if feeds.optional => {
+                warn_!("failed to fetch the {}: {error}", feeds.semester);
+                events
+                    .warnings
+                    .push(format!("{} is not available", feeds.semester));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    let unmatched: Vec<_> = selection
+        .unmatched(&events.value)
+        .iter()
+        .map(|selector| RawStr::new(selector).percent_encode().to_string())
+        .collect();
+    let requested = selection.curses.len() + selection.academic_year_curses.len();
+    if unmatched.len() == requested && requested > 0 {
+        events.warnings.push(format!(
+            "none of the curses exist in {semesters}: {}",
+            unmatched.join(", ")
+        ));
+    } else if !unmatched.is_empty() {
+        events
+            .warnings
+            .push(format!("curses matched no events: {}", unmatched.join(", ")));
+    }
+    Ok(events.map(|events| selection.apply(events)))
 }
 
-async fn get_all_events(semester: Semester) -> Vec<Event> {
-    let mut events = Vec::new();
-    for i in 1..=4 {
+async fn get_semester_events(
+    config: &ScheduleConfig,
+    cache: &FeedCache,
+    feeds: &SemesterFeeds<'_>,
+) -> Result<Cached<Vec<Event>>, Error> {
+    let mut events: Cached<Vec<Event>> = Cached::default();
+    for academic_year in &feeds.academic_years {
         events.append(
-            &mut get_academic_year_events(semester.clone(), i)
-                .await
-                .unwrap_or_default(),
+            get_academic_year_events(config, cache, academic_year, feeds.semester.clone()).await?,
         );
     }
-    events
+    if let Some((from, until)) = feeds.keep {
+        events.value.retain(|event| {
+            let start = event.period.start.date_naive();
+            from <= start && start < until
+        });
+    }
+    Ok(events)
 }
 
-#[cached(time = 900)] // 900s = 15*60s = 15min
-async fn get_academic_year_events(semester: Semester, academic_year: u8) -> Option<Vec<Event>> {
-    let url = MATSE_SCHEDULE_URL.join(&academic_year.to_string()).unwrap();
-    let query = [
-        ("start", semester.get_start_date()?),
-        ("end", semester.get_end_date()?),
-    ];
-    REQWEST_CLIENT
+async fn get_academic_year_events(
+    config: &ScheduleConfig,
+    cache: &FeedCache,
+    academic_year: &AcademicYear,
+    semester: Semester,
+) -> Result<Cached<Vec<Event>>, Error> {
+    let (start, end) = config
+        .semester_windows
+        .window(semester.year, semester.winter_semester)
+        .ok_or(Error::InvalidSemester)?;
+    let fetch = fetch_academic_year_feed(config.feed_url(academic_year)?, start, end);
+    let events = cache
+        .get_or_fetch(&academic_year.id, semester, end, fetch)
+        .await?;
+    Ok(events.map(|events| {
+        events
+            .into_iter()
+            .map(|event| Event {
+                academic_year: academic_year.id.clone(),
+                ..event
+            })
+            .collect()
+    }))
+}
+
+async fn fetch_academic_year_feed(
+    url: Url,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<String, Error> {
+    let query = [("start", start), ("end", end)];
+    Ok(REQWEST_CLIENT
         .get(url)
         .query(&query)
         .send()
-        .await
-        .ok()?.json::<Vec<Event>>()
-        .await
-        .ok()
-}
-
-#[get("/calendar?<winter_semester>&<year>&<curses>")]
-async fn get_calendar<'a>(winter_semester: bool, year: i32, curses: Vec<String>) -> Calendar<'a> {
-    let semester = Semester {
-        year,
-        winter_semester,
-    };
-    let calendar = Calendar::from(get_selected_events(semester, curses).await);
-    calendar
-}
-
-#[get("/eventCategories?<winter_semester>&<year>")]
-async fn get_event_names(winter_semester: bool, year: i32) -> Json<Vec<EventCategories>> {
-    let semester = Semester {
-        year,
-        winter_semester,
-    };
-    let mut event_names = Vec::new();
-    for i in 1..=4 {
-        event_names.push(
-            (
-                ACADEMIC_YEAR_NAMES[i - 1],
-                get_academic_year_events(semester.clone(), i as u8)
-                    .await
-                    .unwrap_or_default()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)
+}
+
+fn parse_events(feed: &str) -> Result<Vec<Event>, Error> {
+    json::from_str(feed).map_err(|error| Error::Deserialize(error.to_string()))
+}
+
+/// The query parameters picking the semesters and events shared by the calendar endpoints.
+#[derive(FromForm)]
+struct CalendarQuery {
+    winter_semester: bool,
+    year: Optional<i32>,
+    current: bool,
+    include_next: bool,
+    cohort: Optional<i32>,
+    from: Optional<QueryDate>,
+    to: Optional<QueryDate>,
+    curses: Vec<Selector>,
+    academic_year_curses: Vec<ScopedSelector>,
+    types: Vec<EventType>,
+    exclude_types: Vec<EventType>,
+    include_holidays: Optional<HolidayScope>,
+}
+
+impl CalendarQuery {
+    /// The semesters of the query, see [`SemesterQuery::resolve`], and the selection of events.
+    fn resolve(self, windows: &SemesterWindows) -> Result<(Semesters, Selection), Error> {
+        let semesters = SemesterQuery {
+            year: self.year.0,
+            winter_semester: self.winter_semester,
+            current: self.current,
+            include_next: self.include_next,
+            cohort: self.cohort.0,
+            from: self.from.0,
+            to: self.to.0,
+        }
+        .resolve(windows)?;
+        let selection = Selection {
+            curses: self.curses,
+            academic_year_curses: self.academic_year_curses,
+            types: self.types,
+            exclude_types: self.exclude_types,
+            include_holidays: self
+                .include_holidays
+                .0
+                .filter(|scope| *scope != HolidayScope::Named),
+        };
+        Ok((semesters, selection))
+    }
+}
+
+/// A [`CalendarQuery`] and the options of an endpoint, both parsed from the same query
+/// parameters as a route only takes one trailing `<param..>`.
+struct WithOptions<O> {
+    query: CalendarQuery,
+    options: O,
+}
+
+#[rocket::async_trait]
+impl<'v, O: form::FromForm<'v>> form::FromForm<'v> for WithOptions<O> {
+    type Context = (<CalendarQuery as form::FromForm<'v>>::Context, O::Context);
+
+    fn init(opts: form::Options) -> Self::Context {
+        (CalendarQuery::init(opts), O::init(opts))
+    }
+
+    fn push_value((query, options): &mut Self::Context, field: ValueField<'v>) {
+        CalendarQuery::push_value(query, field.clone());
+        O::push_value(options, field);
+    }
+
+    async fn push_data((query, _): &mut Self::Context, field: DataField<'v, '_>) {
+        // only multipart forms have data fields, never query strings
+        CalendarQuery::push_data(query, field).await;
+    }
+
+    fn finalize((query, options): Self::Context) -> form::Result<'v, Self> {
+        match (CalendarQuery::finalize(query), O::finalize(options)) {
+            (Ok(query), Ok(options)) => Ok(Self { query, options }),
+            (Err(mut errors), Err(more)) => {
+                errors.extend(more);
+                Err(errors)
+            }
+            (Err(errors), _) | (_, Err(errors)) => Err(errors),
+        }
+    }
+}
+
+#[get("/calendar?<query..>")]
+async fn get_calendar<'a>(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    revisions: &State<Revisions>,
+    query: form::Result<'_, WithOptions<CalendarOptions>>,
+) -> Result<Cached<Calendar<'a>>, Error> {
+    let WithOptions { query, options } = query?;
+    let (semesters, selection) = query.resolve(&config.semester_windows)?;
+    calendar(config, cache, revisions, &semesters, &selection, &options).await
+}
+
+async fn calendar<'a>(
+    config: &ScheduleConfig,
+    cache: &FeedCache,
+    revisions: &Revisions,
+    semesters: &Semesters,
+    selection: &Selection,
+    options: &CalendarOptions,
+) -> Result<Cached<Calendar<'a>>, Error> {
+    let Cached {
+        value,
+        stale,
+        warnings,
+    } = get_selected_events(config, cache, semesters, selection).await?;
+    Ok(Cached {
+        value: Calendar::new(config, revisions, value, options).await,
+        stale,
+        warnings,
+    })
+}
+
+/// Parses a stored, still percent-encoded `/calendar` query string.
+///
+/// A rolling `current` semester is resolved on every call, so stored subscriptions move on with
+/// the semesters.
+fn parse_calendar_query(
+    config: &ScheduleConfig,
+    query: &str,
+) -> Result<(Semesters, Selection, CalendarOptions), Error> {
+    let WithOptions { query, options } =
+        Form::<WithOptions<CalendarOptions>>::parse_encoded(RawStr::new(query))?;
+    let (semesters, selection) = query.resolve(&config.semester_windows)?;
+    Ok((semesters, selection, options))
+}
+
+/// Stores the `/calendar` query string in the body and answers with the short link to it.
+#[post("/subscriptions", data = "<query>")]
+async fn create_subscription(
+    config: &State<ScheduleConfig>,
+    subscriptions: &State<Subscriptions>,
+    query: String,
+) -> Result<Created<String>, Error> {
+    let query = query.trim().trim_start_matches('?');
+    parse_calendar_query(config, query)?;
+    let token = subscriptions.insert(query, config.max_subscriptions).await?;
+    let link = format!("/c/{token}.ics");
+    Ok(Created::new(link.clone()).body(link))
+}
+
+/// The calendar of a stored query, the same as `/calendar` with that query.
+#[get("/c/<token>")]
+async fn get_subscription<'a>(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    revisions: &State<Revisions>,
+    subscriptions: &State<Subscriptions>,
+    token: &str,
+) -> Result<Cached<Calendar<'a>>, Error> {
+    let token = token.strip_suffix(".ics").unwrap_or(token);
+    let query = subscriptions
+        .get(token)
+        .ok_or(Error::UnknownSubscription)?;
+    let (semesters, selection, options) = parse_calendar_query(config, &query)?;
+    calendar(config, cache, revisions, &semesters, &selection, &options).await
+}
+
+/// Printable weekly timetable of the selected courses.
+#[get("/timetable?<query..>")]
+async fn get_timetable(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    query: form::Result<'_, CalendarQuery>,
+) -> Result<Cached<RawHtml<String>>, Error> {
+    let (semesters, selection) = query?.resolve(&config.semester_windows)?;
+    let title = format!("Stundenplan {semesters}");
+    let events = get_selected_events(config, cache, &semesters, &selection).await?;
+    Ok(events.map(|events| RawHtml(timetable::render(&title, &events))))
+}
+
+#[get("/calendar.csv?<query..>")]
+async fn get_calendar_csv(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    query: form::Result<'_, WithOptions<CsvOptions>>,
+) -> Result<Cached<Csv>, Error> {
+    let WithOptions { query, options } = query?;
+    let (semesters, selection) = query.resolve(&config.semester_windows)?;
+    let events = get_selected_events(config, cache, &semesters, &selection).await?;
+    Ok(events.map(|events| Csv { events, options }))
+}
+
+/// The events of the semester as JSON, limited to `curses` and `types` if any are given.
+#[get("/events?<query..>")]
+async fn get_events(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    query: form::Result<'_, CalendarQuery>,
+) -> Result<Cached<Json<Vec<Event>>>, Error> {
+    let (semesters, mut selection) = query?.resolve(&config.semester_windows)?;
+    if selection.is_empty() {
+        selection.types = EventType::ALL.to_vec();
+    }
+    let events = get_selected_events(config, cache, &semesters, &selection).await?;
+    Ok(events.map(Json))
+}
+
+#[get("/eventCategories?<semester..>")]
+async fn get_event_names(
+    config: &State<ScheduleConfig>,
+    cache: &State<FeedCache>,
+    semester: form::Result<'_, Semester>,
+) -> Result<Cached<Json<Vec<EventCategories>>>, Error> {
+    let semester = semester?;
+    let mut event_names = Cached::default();
+    for academic_year in &config.academic_years {
+        let curses = get_academic_year_events(config, cache, academic_year, semester.clone())
+            .await?
+            .map(|events| {
+                events
                     .into_iter()
-                    .filter(|event| !event.is_holiday)
+                    .filter(|event| !event.is_holiday && !event.is_cancelled)
                     .map(|event| event.name)
-                    .collect(),
-            )
-                .into(),
-        );
+                    .collect()
+            });
+        event_names.append(curses.map(|curses| [(academic_year.name.clone(), curses).into()]));
     }
-    Json(event_names)
+    Ok(event_names.map(Json))
 }
 
 #[launch]
 fn rocket() -> _ {
-    rocket::build().mount("/", routes![get_calendar, get_event_names])
+    rocket::build()
+        .mount("/", routes![
+            get_calendar,
+            get_calendar_csv,
+            get_timetable,
+            get_events,
+            get_event_names,
+            create_subscription,
+            get_subscription
+        ])
+        .register("/", catchers![error::catch_default])
+        .attach(AdHoc::config::<ScheduleConfig>())
+        .attach(AdHoc::try_on_ignite("Cache", |rocket| async {
+            let Some(config) = rocket.state::<ScheduleConfig>() else {
+                return Err(rocket);
+            };
+            let (ttl, grace_period) = (config.cache_ttl(), config.cancellation_grace_period());
+            let state = match &config.cache_dir {
+                Some(dir) => FeedCache::load(ttl, grace_period, dir.join("feeds")).and_then(
+                    |cache| {
+                        Ok((
+                            cache,
+                            Revisions::load(dir.join("revisions.json"))?,
+                            Subscriptions::load(dir.join("subscriptions.json"))?,
+                        ))
+                    },
+                ),
+                None => Ok((
+                    FeedCache::new(ttl, grace_period),
+                    Revisions::default(),
+                    Subscriptions::default(),
+                )),
+            };
+            match state {
+                Ok((cache, revisions, subscriptions)) => Ok(rocket
+                    .manage(cache)
+                    .manage(revisions)
+                    .manage(subscriptions)),
+                Err(error) => {
+                    error!("failed to load cache: {error}");
+                    Err(rocket)
+                }
+            }
+        }))
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeDelta};
+use chrono_tz::Tz;
+
+use crate::{uid, Event};
+
+/// Entry of a calendar, either a single event or a weekly series of events.
+pub enum Item {
+    Single(Event),
+    Series(Series),
+}
+
+/// Events taking place every week at the same time with the same content.
+///
+/// `event` is the first occurrence, the others are described by the recurrence rule. Weeks
+/// without an occurrence are excluded, unless an event of the same course took place in that
+/// week at a different time, which then overrides the occurrence.
+pub struct Series {
+    pub event: Event,
+    /// Start of the last occurrence.
+    pub until: DateTime<Tz>,
+    /// Local start times of the occurrences that do not take place.
+    pub excluded: Vec<NaiveDateTime>,
+    /// Moved occurrences, by the local start time they replace.
+    pub overrides: Vec<(NaiveDateTime, Event)>,
+    fingerprints: Vec<u64>,
+}
+
+impl Series {
+    /// Changes whenever any occurrence does.
+    pub fn fingerprint(&self) -> u64 {
+        let parts: Vec<_> = self
+            .fingerprints
+            .iter()
+            .map(u64::to_string)
+            .chain(self.excluded.iter().map(ToString::to_string))
+            .collect();
+        uid::fnv1a(&parts.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+}
+
+/// Collapses weekly repeating events into series, keeping the order of first occurrences.
+///
+/// All-day and cancelled events always stay single events, as do duplicates of an occurrence.
+pub fn collapse(events: Vec<Event>) -> Vec<Item> {
+    let mut groups: Vec<Vec<Event>> = Vec::new();
+    let mut group_of: HashMap<u64, usize> = HashMap::new();
+    for event in events {
+        let key = (!event.is_all_day && !event.is_cancelled).then(|| series_key(&event));
+        match key.and_then(|key| group_of.get(&key)) {
+            Some(&index) => groups[index].push(event),
+            None => {
+                if let Some(key) = key {
+                    group_of.insert(key, groups.len());
+                }
+                groups.push(vec![event]);
+            }
+        }
+    }
+
+    let mut items = Vec::new();
+    for mut group in groups {
+        group.sort_by_key(|event| event.period.start);
+        let mut occurrences: Vec<Event> = Vec::new();
+        let mut duplicates = Vec::new();
+        for event in group {
+            match occurrences.last() {
+                Some(previous) if previous.period.start == event.period.start => {
+                    duplicates.push(event)
+                }
+                _ => occurrences.push(event),
+            }
+        }
+        if occurrences.len() < 2 {
+            items.extend(occurrences.into_iter().map(Item::Single));
+        } else {
+            items.push(Item::Series(series(occurrences)));
+        }
+        items.extend(duplicates.into_iter().map(Item::Single));
+    }
+
+    // a single event of a series' course in a week the series skips is a moved occurrence
+    let mut moves: Vec<(usize, usize, NaiveDateTime)> = Vec::new();
+    for (single, item) in items.iter().enumerate() {
+        let Item::Single(event) = item else {
+            continue;
+        };
+        if event.is_all_day || event.is_cancelled {
+            continue;
+        }
+        let week = event.period.start.date_naive().iso_week();
+        let replaced = items
+            .iter()
+            .enumerate()
+            .find_map(|(series, item)| match item {
+                Item::Series(candidate)
+                    if candidate.event.academic_year == event.academic_year
+                        && candidate.event.name == event.name =>
+                {
+                    candidate
+                        .excluded
+                        .iter()
+                        .find(|start| {
+                            start.date().iso_week() == week
+                                && !moves
+                                    .iter()
+                                    .any(|(_, other, taken)| (*other, *taken) == (series, **start))
+                        })
+                        .map(|start| (single, series, *start))
+                }
+                _ => None,
+            });
+        moves.extend(replaced);
+    }
+    let mut items: Vec<Option<Item>> = items.into_iter().map(Some).collect();
+    for (single, series, start) in moves {
+        let Some(Item::Single(event)) = items[single].take() else {
+            continue;
+        };
+        if let Some(Item::Series(series)) = &mut items[series] {
+            series.excluded.retain(|excluded| *excluded != start);
+            series.fingerprints.push(event.fingerprint());
+            series.overrides.push((start, event));
+        }
+    }
+    items.into_iter().flatten().collect()
+}
+
+/// Builds the series of at least two `occurrences`, sorted by their start.
+fn series(mut occurrences: Vec<Event>) -> Series {
+    let starts: Vec<_> = occurrences
+        .iter()
+        .map(|event| event.period.start.naive_local())
+        .collect();
+    let (first, last) = (starts[0], starts[starts.len() - 1]);
+    let excluded = (0..)
+        .map(|week| first + TimeDelta::weeks(week))
+        .take_while(|start| *start < last)
+        .filter(|start| !starts.contains(start))
+        .collect();
+    Series {
+        fingerprints: occurrences.iter().map(Event::fingerprint).collect(),
+        until: occurrences[occurrences.len() - 1].period.start,
+        event: occurrences.swap_remove(0),
+        excluded,
+        overrides: Vec::new(),
+    }
+}
+
+/// Identifies events that could be occurrences of the same series.
+fn series_key(event: &Event) -> u64 {
+    let (start, end) = (event.period.start, event.period.end);
+    uid::fnv1a(&[
+        &event.academic_year,
+        &event.name,
+        &start.weekday().to_string(),
+        &start.time().to_string(),
+        &(end.naive_local() - start.naive_local()).to_string(),
+        &event.location.to_string(),
+        &event.lecturer.to_string(),
+        event.information.as_deref().unwrap_or_default(),
+        &format!(
+            "{}{}{}",
+            event.is_holiday, event.is_exercise, event.is_lecture
+        ),
+    ])
+}
@@ -0,0 +1,79 @@
+use std::{io, path::PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::store::JsonStore;
+
+/// Remembers the content of every published event, so DTSTAMP, LAST-MODIFIED and SEQUENCE
+/// only change when the event itself did and clients can sync efficiently.
+#[derive(Default)]
+pub struct Revisions(JsonStore<Revision>);
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Revision {
+    fingerprint: u64,
+    /// Time of the last change in UTC.
+    pub modified: NaiveDateTime,
+    pub sequence: u32,
+    /// Day the event was last published, in UTC.
+    #[serde(default = "today")]
+    seen: NaiveDate,
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+impl Revisions {
+    /// Creates revisions persisted in the file at `path`, loading it if it exists.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        JsonStore::load(path).map(Self)
+    }
+
+    /// Returns the current revision of each `(uid, fingerprint)`, starting a new one for
+    /// events that are new or whose fingerprint changed.
+    ///
+    /// Revisions of events not published for longer than `retention` are dropped, so the
+    /// revisions do not grow with every event ever published.
+    pub async fn update(&self, events: &[(String, u64)], retention: TimeDelta) -> Vec<Revision> {
+        let now = Utc::now().naive_utc();
+        let today = now.date();
+        self.0
+            .update(|entries| {
+                let mut changed = false;
+                let revisions = events
+                    .iter()
+                    .map(|(uid, fingerprint)| {
+                        let revision = entries.entry(uid.clone()).or_insert_with(|| {
+                            changed = true;
+                            Revision {
+                                fingerprint: *fingerprint,
+                                modified: now,
+                                sequence: 0,
+                                seen: today,
+                            }
+                        });
+                        if revision.fingerprint != *fingerprint {
+                            changed = true;
+                            *revision = Revision {
+                                fingerprint: *fingerprint,
+                                modified: now,
+                                sequence: revision.sequence + 1,
+                                seen: today,
+                            };
+                        }
+                        if revision.seen != today {
+                            changed = true;
+                            revision.seen = today;
+                        }
+                        *revision
+                    })
+                    .collect();
+                let count = entries.len();
+                entries.retain(|_, revision| today - revision.seen <= retention);
+                (revisions, changed || entries.len() != count)
+            })
+            .await
+    }
+}
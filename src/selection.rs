@@ -0,0 +1,229 @@
+use std::{collections::HashSet, fmt};
+
+use regex::Regex;
+use rocket::form::{self, FromFormField, ValueField};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{Event, EventType};
+
+/// The events a subscriber picked from the feeds of a semester.
+///
+/// An event is selected if its name matches one of `curses` or `academic_year_curses` and, if any
+/// are given, its type is one of `types`. Without curses every event of one of `types` is
+/// selected. Events of `exclude_types` never are. Holidays can additionally be included without
+/// naming them.
+#[derive(Clone, Default)]
+pub struct Selection {
+    pub curses: Vec<Selector>,
+    pub academic_year_curses: Vec<ScopedSelector>,
+    pub types: Vec<EventType>,
+    pub exclude_types: Vec<EventType>,
+    pub include_holidays: Option<HolidayScope>,
+}
+
+/// Matches course names, picked by the prefix of the query value.
+///
+/// Without a prefix the name has to be equal after normalization, `prefix:` only compares the
+/// beginning and `glob:` supports `*` and `?` wildcards, all of them normalized. `regex:` is
+/// matched against the name as published, anywhere in it unless anchored.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    source: String,
+    kind: SelectorKind,
+}
+
+#[derive(Clone, Debug)]
+enum SelectorKind {
+    Exact(String),
+    Prefix(String),
+    /// Matched against the normalized name.
+    Glob(Regex),
+    Regex(Regex),
+}
+
+impl Selector {
+    pub fn parse(source: &str) -> Result<Self, regex::Error> {
+        let kind = if let Some(prefix) = source.strip_prefix("prefix:") {
+            SelectorKind::Prefix(normalize(prefix))
+        } else if let Some(glob) = source.strip_prefix("glob:") {
+            let pattern: String = normalize(glob)
+                .chars()
+                .map(|c| match c {
+                    '*' => ".*".to_string(),
+                    '?' => ".".to_string(),
+                    c => regex::escape(&c.to_string()),
+                })
+                .collect();
+            SelectorKind::Glob(Regex::new(&format!("^{pattern}$"))?)
+        } else if let Some(pattern) = source.strip_prefix("regex:") {
+            SelectorKind::Regex(Regex::new(pattern)?)
+        } else {
+            SelectorKind::Exact(normalize(source))
+        };
+        Ok(Self {
+            source: source.to_string(),
+            kind,
+        })
+    }
+
+    /// The query value the selector was parsed from.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match &self.kind {
+            SelectorKind::Exact(exact) => normalize(name) == *exact,
+            SelectorKind::Prefix(prefix) => normalize(name).starts_with(prefix.as_str()),
+            SelectorKind::Glob(glob) => glob.is_match(&normalize(name)),
+            SelectorKind::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl<'v> FromFormField<'v> for Selector {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        Self::parse(field.value).map_err(|error| form::Error::validation(error.to_string()).into())
+    }
+}
+
+/// A [`Selector`] only matching events of one academic year feed, written `<id>:<selector>`.
+#[derive(Clone, Debug)]
+pub struct ScopedSelector {
+    pub academic_year: String,
+    pub selector: Selector,
+}
+
+impl ScopedSelector {
+    pub fn matches(&self, event: &Event) -> bool {
+        event.academic_year == self.academic_year && self.selector.matches(&event.name)
+    }
+}
+
+impl fmt::Display for ScopedSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.academic_year, self.selector.as_str())
+    }
+}
+
+impl<'v> FromFormField<'v> for ScopedSelector {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let Some((academic_year, selector)) = field.value.split_once(':') else {
+            return Err(form::Error::validation("expected <academic year>:<course>").into());
+        };
+        Ok(Self {
+            academic_year: academic_year.to_string(),
+            selector: Selector::parse(selector)
+                .map_err(|error| form::Error::validation(error.to_string()))?,
+        })
+    }
+}
+
+/// Folds the differences of course names typed by hand: Unicode compatibility forms, case,
+/// repeated or surrounding whitespace and the various dashes.
+fn normalize(name: &str) -> String {
+    name.nfkc()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Academic years whose holidays are included in a [`Selection`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum HolidayScope {
+    /// No academic years, holidays are only selected like other events, as without the option.
+    #[field(value = "false")]
+    Named,
+    /// The academic years of the selected events.
+    #[field(value = "true")]
+    #[field(value = "selected")]
+    Selected,
+    #[field(value = "all")]
+    All,
+}
+
+impl Selection {
+    /// Whether nothing is picked explicitly, only types are excluded.
+    pub fn is_empty(&self) -> bool {
+        self.curses.is_empty()
+            && self.academic_year_curses.is_empty()
+            && self.types.is_empty()
+            && self.include_holidays.is_none()
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        let event_type = event.event_type();
+        if self.exclude_types.contains(&event_type) {
+            return false;
+        }
+        if self.curses.is_empty() && self.academic_year_curses.is_empty() {
+            return self.types.contains(&event_type);
+        }
+        (self.types.is_empty() || self.types.contains(&event_type))
+            && (self
+                .curses
+                .iter()
+                .any(|selector| selector.matches(&event.name))
+                || self
+                    .academic_year_curses
+                    .iter()
+                    .any(|selector| selector.matches(event)))
+    }
+
+    /// The `curses` and `academic_year_curses` that match none of `events`, usually all events
+    /// of the semester, as given in the query.
+    pub fn unmatched(&self, events: &[Event]) -> Vec<String> {
+        let curses = self
+            .curses
+            .iter()
+            .filter(|selector| !events.iter().any(|event| selector.matches(&event.name)))
+            .map(|selector| selector.as_str().to_string());
+        let academic_year_curses = self
+            .academic_year_curses
+            .iter()
+            .filter(|selector| !events.iter().any(|event| selector.matches(event)))
+            .map(ScopedSelector::to_string);
+        curses.chain(academic_year_curses).collect()
+    }
+
+    /// The selected events in their original order.
+    ///
+    /// Holidays are only selected once, even if several academic years list them.
+    pub fn apply(&self, events: Vec<Event>) -> Vec<Event> {
+        let academic_years: HashSet<_> = events
+            .iter()
+            .filter(|event| !event.is_holiday && self.matches(event))
+            .map(|event| event.academic_year.clone())
+            .collect();
+        let included = |event: &Event| {
+            self.include_holidays.is_some_and(|scope| {
+                event.is_holiday
+                    && !self.exclude_types.contains(&EventType::Holiday)
+                    && (scope == HolidayScope::All || academic_years.contains(&event.academic_year))
+            })
+        };
+        let mut holidays = HashSet::new();
+        events
+            .into_iter()
+            .filter(|event| {
+                (self.matches(event) || included(event))
+                    && (!event.is_holiday || holidays.insert(holiday_key(event)))
+            })
+            .collect()
+    }
+}
+
+/// Identifies a holiday across the academic year feeds listing it.
+pub fn holiday_key(event: &Event) -> (String, String, String) {
+    (
+        event.name.clone(),
+        event.get_start_date(),
+        event.get_end_date(),
+    )
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rocket::{
+    serde::json,
+    tokio::{fs as async_fs, sync::Mutex as AsyncMutex},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Map kept in memory and, if it has a path, persisted as a JSON file after every change.
+pub struct JsonStore<V> {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<String, V>>,
+    /// Held while persisting, so concurrent changes are written in order.
+    persisting: AsyncMutex<()>,
+}
+
+impl<V> Default for JsonStore<V> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            entries: Mutex::default(),
+            persisting: AsyncMutex::default(),
+        }
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> JsonStore<V> {
+    /// Creates a store persisted in the file at `path`, loading it if it exists.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let entries = match fs::read_to_string(&path) {
+            Ok(entries) => json::from_str(&entries)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Self {
+            path: Some(path),
+            entries: Mutex::new(entries),
+            persisting: AsyncMutex::default(),
+        })
+    }
+
+    pub fn is_persisted(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&HashMap<String, V>) -> R) -> R {
+        f(&self.entries.lock().unwrap())
+    }
+
+    /// Applies `change`, which returns its result and whether it changed any entry, and
+    /// persists the entries if it did. Failing to persist is only logged.
+    pub async fn update<R>(&self, change: impl FnOnce(&mut HashMap<String, V>) -> (R, bool)) -> R {
+        let _persisting = self.persisting.lock().await;
+        let (result, persisted) = {
+            let mut entries = self.entries.lock().unwrap();
+            let (result, changed) = change(&mut entries);
+            let persisted = match &self.path {
+                Some(path) if changed => Some((path, json::to_string(&*entries))),
+                _ => None,
+            };
+            (result, persisted)
+        };
+        if let Some((path, entries)) = persisted {
+            let persisted = match entries {
+                Ok(entries) => write_atomically(path, entries).await,
+                Err(error) => Err(error.into()),
+            };
+            if let Err(error) = persisted {
+                warn_!("failed to persist {}: {error}", path.display());
+            }
+        }
+        result
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it, so readers never see
+/// a partially written file.
+pub async fn write_atomically(path: &Path, contents: String) -> io::Result<()> {
+    let temporary = path.with_extension("json.tmp");
+    async_fs::write(&temporary, contents).await?;
+    async_fs::rename(temporary, path).await
+}
@@ -0,0 +1,53 @@
+use std::{io, path::PathBuf};
+
+use crate::{error::Error, store::JsonStore, uid};
+
+/// `/calendar` queries stored under short tokens, so subscription links stay short enough for
+/// clients that truncate long URLs.
+#[derive(Default)]
+pub struct Subscriptions(JsonStore<String>);
+
+impl Subscriptions {
+    /// Creates subscriptions persisted in the file at `path`, loading it if it exists.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        JsonStore::load(path).map(Self)
+    }
+
+    /// Stores `query` and returns its token. The same query always gets the same token.
+    ///
+    /// If the token is taken by another query, it is rehashed with an increasing counter until a
+    /// free token or the token of this query is found.
+    ///
+    /// New queries are refused if the subscriptions are not persisted, as their links would break
+    /// on the next restart, or if `limit` queries are stored already.
+    pub async fn insert(&self, query: &str, limit: usize) -> Result<String, Error> {
+        if !self.0.is_persisted() {
+            return Err(Error::SubscriptionsUnavailable(
+                "subscriptions require cache_dir".to_string(),
+            ));
+        }
+        self.0
+            .update(|queries| {
+                let mut token = format!("{:016x}", uid::fnv1a(&[query]));
+                let mut attempt = 0u32;
+                while queries.get(&token).is_some_and(|stored| stored != query) {
+                    attempt += 1;
+                    token = format!("{:016x}", uid::fnv1a(&[query, &attempt.to_string()]));
+                }
+                if queries.contains_key(&token) {
+                    (Ok(token), false)
+                } else if queries.len() >= limit {
+                    let error = format!("the limit of {limit} subscriptions is reached");
+                    (Err(Error::SubscriptionsUnavailable(error)), false)
+                } else {
+                    queries.insert(token.clone(), query.to_string());
+                    (Ok(token), true)
+                }
+            })
+            .await
+    }
+
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.0.read(|queries| queries.get(token).cloned())
+    }
+}
@@ -0,0 +1,1700 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, TimeZone};
+use rocket::{
+    figment::Figment,
+    http::{Accept, ContentType, Status},
+    local::asynchronous::Client,
+    serde::json::{self, Value},
+};
+
+use super::*;
+
+const YEAR_1: &str = include_str!("tests/fixtures/year_1.json");
+const YEAR_1_RESCHEDULED: &str = include_str!("tests/fixtures/year_1_rescheduled.json");
+const YEAR_2: &str = include_str!("tests/fixtures/year_2.json");
+const DST_TRANSITIONS: &str = include_str!("tests/fixtures/dst_transitions.json");
+const WEEKLY: &str = include_str!("tests/fixtures/weekly.json");
+const EMPTY: &str = "[]";
+
+/// Feed id, response status and response body.
+type Feed = (&'static str, u16, &'static str);
+
+/// Minimal stand-in for the upstream eventFeed, serving one fixture per academic year id.
+struct MockFeed {
+    url: String,
+    feeds: Arc<Mutex<Vec<Feed>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockFeed {
+    fn start(feeds: &[Feed]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/eventFeed/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let feeds = Arc::new(Mutex::new(feeds.to_vec()));
+        let served = feeds.clone();
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let target = request_line.split(' ').nth(1).unwrap_or_default();
+                recorded.lock().unwrap().push(target.to_string());
+                let id = target
+                    .trim_start_matches("/eventFeed/")
+                    .split('?')
+                    .next()
+                    .unwrap_or_default();
+                let feed = served
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(feed_id, _, _)| *feed_id == id)
+                    .copied();
+                let response = match feed {
+                    Some((_, status, body)) => format!(
+                        "HTTP/1.1 {status} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".into(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        Self {
+            url,
+            feeds,
+            requests,
+        }
+    }
+
+    fn standard() -> Self {
+        Self::start(&[
+            ("1", 200, YEAR_1),
+            ("2", 200, YEAR_2),
+            ("3", 200, EMPTY),
+            ("4", 200, EMPTY),
+        ])
+    }
+
+    /// Serves `fixture` for the first academic year and no events for the others.
+    fn with_year_1(fixture: &'static str) -> Self {
+        Self::start(&[
+            ("1", 200, fixture),
+            ("2", 200, EMPTY),
+            ("3", 200, EMPTY),
+            ("4", 200, EMPTY),
+        ])
+    }
+
+    fn config(&self) -> ScheduleConfig {
+        ScheduleConfig {
+            schedule_url: Url::parse(&self.url).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn respond(&self, id: &'static str, status: u16, body: &'static str) {
+        let mut feeds = self.feeds.lock().unwrap();
+        feeds.retain(|(feed_id, _, _)| *feed_id != id);
+        feeds.push((id, status, body));
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn client(&self) -> Client {
+        client(figment(&self.url)).await
+    }
+}
+
+fn figment(schedule_url: &str) -> Figment {
+    rocket::Config::figment().merge(("schedule_url", schedule_url))
+}
+
+async fn client(figment: Figment) -> Client {
+    Client::tracked(rocket().configure(figment)).await.unwrap()
+}
+
+async fn body(client: &Client, uri: &str) -> String {
+    client
+        .get(uri)
+        .dispatch()
+        .await
+        .into_string()
+        .await
+        .unwrap()
+}
+
+async fn error_body(client: &Client, uri: &str, status: Status) -> Value {
+    let response = client.get(uri).dispatch().await;
+    assert_eq!(response.status(), status);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    json::from_str(&response.into_string().await.unwrap()).unwrap()
+}
+
+fn winter_2023() -> Semester {
+    Semester {
+        year: 2023,
+        winter_semester: true,
+    }
+}
+
+async fn get_year_1(
+    config: &ScheduleConfig,
+    cache: &FeedCache,
+) -> Result<Cached<Vec<Event>>, Error> {
+    get_academic_year_events(config, cache, &config.academic_years[0], winter_2023()).await
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("matse_calendar-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn strip_bang_removes_prefix() {
+    let events = parse_events(YEAR_1).unwrap();
+    assert_eq!(events[0].name, "Analysis");
+    assert_eq!(events[1].name, "Analysis Übung");
+}
+
+#[test]
+fn bool_from_str_option_accepts_missing_and_empty_values() {
+    let events = parse_events(YEAR_1).unwrap();
+    assert!(events[0].is_lecture && !events[0].is_exercise && !events[0].is_holiday);
+    assert!(!events[1].is_lecture && events[1].is_exercise && !events[1].is_holiday);
+    assert!(events[2].is_holiday && events[2].is_all_day);
+}
+
+#[test]
+fn from_berlin_converts_to_utc() {
+    let events = parse_events(YEAR_1).unwrap();
+    // summer time, UTC+2
+    assert_eq!(events[0].get_start_date(), "20231002T060000Z");
+    assert_eq!(events[0].get_end_date(), "20231002T073000Z");
+    // winter time, UTC+1
+    assert_eq!(events[1].get_start_date(), "20231106T080000Z");
+    assert_eq!(
+        timezone::format_local(&events[1].period.start),
+        "20231106T090000"
+    );
+}
+
+#[test]
+fn from_local_resolves_dst_transitions() {
+    let local = |date: &str| timezone::from_local(Berlin, &date.parse().unwrap());
+    // ambiguous when the clocks fall back, the earlier instant wins
+    let (earlier, resolution) = local("2023-10-29T02:30:00");
+    assert_eq!(earlier.naive_utc().to_string(), "2023-10-29 00:30:00");
+    assert_eq!(resolution, Resolution::Ambiguous);
+    // nonexistent when the clocks spring forward, shifted behind the gap
+    let (shifted, resolution) = local("2023-03-26T02:30:00");
+    assert_eq!(shifted.naive_utc().to_string(), "2023-03-26 01:30:00");
+    assert_eq!(timezone::format_local(&shifted), "20230326T033000");
+    assert_eq!(resolution, Resolution::Nonexistent);
+    assert_eq!(local("2023-03-26T03:30:00").1, Resolution::Exact);
+}
+
+#[test]
+fn all_day_dates_are_berlin_dates_with_exclusive_end() {
+    let dates = |start: &str, end: &str| {
+        Period::from(UpstreamPeriod {
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+        })
+        .dates()
+    };
+    let date = |date: &str| date.parse::<NaiveDate>().unwrap();
+    assert_eq!(
+        dates("2023-12-23T00:00:00", "2024-01-06T23:59:59"),
+        (date("2023-12-23"), date("2024-01-07"))
+    );
+    assert_eq!(
+        dates("2023-12-23T00:00:00", "2024-01-07T00:00:00"),
+        (date("2023-12-23"), date("2024-01-07"))
+    );
+    // a single day, sometimes sent with the end equal to the start
+    assert_eq!(
+        dates("2023-10-03T00:00:00", "2023-10-03T00:00:00"),
+        (date("2023-10-03"), date("2023-10-04"))
+    );
+    // the day of the clocks changing is still a single day
+    assert_eq!(
+        dates("2023-10-29T00:00:00", "2023-10-29T23:59:59"),
+        (date("2023-10-29"), date("2023-10-30"))
+    );
+}
+
+#[test]
+fn events_in_dst_transitions_are_adjusted() {
+    let events = parse_events(DST_TRANSITIONS).unwrap();
+    let times: Vec<_> = events
+        .iter()
+        .map(|event| {
+            (
+                event.get_start_date(),
+                event.get_end_date(),
+                event.period.dst_adjusted,
+            )
+        })
+        .collect();
+    assert_eq!(
+        times,
+        [
+            ("20230326T003000Z".into(), "20230326T013000Z".into(), true),
+            // the shifted start would be after the end, the duration is kept instead
+            ("20230326T013000Z".into(), "20230326T020000Z".into(), true),
+            ("20231029T000000Z".into(), "20231029T004500Z".into(), true),
+            ("20230327T060000Z".into(), "20230327T073000Z".into(), false),
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn calendar_flags_dst_adjusted_events() {
+    let feed = MockFeed::with_year_1(DST_TRANSITIONS);
+    let client = feed.client().await;
+    let body = body(
+        &client,
+        "/calendar?winter_semester=false&year=2023&curses=Zeitumstellung&curses=Analysis",
+    )
+    .await;
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 2);
+    assert_eq!(body.matches("X-MATSE-DST-ADJUSTED:TRUE\r\n").count(), 1);
+    assert!(body.contains("DESCRIPTION:Die Uhrzeit dieses Termins fällt in die Zeitumstellung"));
+}
+
+#[test]
+fn vtimezone_lists_transitions() {
+    let from = Utc.with_ymd_and_hms(2023, 10, 1, 0, 0, 0).unwrap();
+    let to = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+    let mut calendar = ICalendar::new("2.0", "test");
+    calendar.add_timezone(timezone::vtimezone(Berlin, from, to));
+    let calendar = calendar.to_string();
+    assert!(calendar.contains("TZID:Europe/Berlin\r\n"));
+    assert!(calendar.contains(
+        "BEGIN:DAYLIGHT\r\nDTSTART:20231001T020000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0200\r\nTZNAME:CEST\r\nEND:DAYLIGHT"
+    ));
+    assert!(calendar.contains(
+        "BEGIN:STANDARD\r\nDTSTART:20231029T030000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\nTZNAME:CET\r\nEND:STANDARD"
+    ));
+    assert!(calendar.contains(
+        "BEGIN:DAYLIGHT\r\nDTSTART:20240331T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nTZNAME:CEST\r\nEND:DAYLIGHT"
+    ));
+}
+
+#[test]
+fn missing_location_and_lecturer_fields() {
+    let events = parse_events(YEAR_1).unwrap();
+    assert!(!events[1].location.contains_information());
+    assert!(events[1].lecturer.organizer().is_none());
+    assert_eq!(
+        events[0].location.to_string(),
+        "Hörsaal 1\nTemplergraben 55\nErdgeschoss"
+    );
+    assert_eq!(
+        events[0].lecturer.to_string(),
+        "CN=Erika Mustermann:MAILTO:mustermann@example.com"
+    );
+}
+
+#[test]
+fn uids_use_upstream_id() {
+    let mut events = parse_events(YEAR_1).unwrap();
+    events[0].academic_year = "1".into();
+    let mut uids = Uids::new("example.com");
+    assert_eq!(uids.next(&events[0]), "1-1001@example.com");
+    // a rescheduled event keeps its UID
+    events[0].period.start += chrono::Duration::days(1);
+    assert_eq!(
+        Uids::new("example.com").next(&events[0]),
+        "1-1001@example.com"
+    );
+}
+
+#[test]
+fn uids_without_upstream_id_are_stable_and_unique() {
+    let events: Vec<_> = parse_events(YEAR_2)
+        .unwrap()
+        .into_iter()
+        .map(|event| Event {
+            academic_year: "2".into(),
+            ..event
+        })
+        .collect();
+    let mut uids = Uids::new("example.com");
+    let first = uids.next(&events[0]);
+    let second = uids.next(&events[1]);
+    assert_eq!(first, "2-70fc03cf0accb9e3@example.com");
+    assert_eq!(second, "2-eddd96e45ccb0c74@example.com");
+    assert_eq!(Uids::new("example.com").next(&events[0]), first);
+    // a parallel group that drops out and is appended as cancelled keeps its UID
+    let mut uids = Uids::new("example.com");
+    assert_eq!(uids.next(&events[1]), second);
+    assert_eq!(uids.next(&events[0]), first);
+    // identical events are told apart by a counter
+    let mut uids = Uids::new("example.com");
+    uids.next(&events[0]);
+    assert_eq!(uids.next(&events[0]), first.replace("@", "-2@"));
+
+    let mut other_year = events[0].clone();
+    other_year.academic_year = "3".into();
+    assert!(!Uids::new("example.com")
+        .next(&other_year)
+        .contains("70fc03cf0accb9e3"));
+}
+
+#[rocket::async_test]
+async fn calendar_uids_use_configured_domain() {
+    let feed = MockFeed::standard();
+    let client = client(figment(&feed.url).merge(("uid_domain", "calendar.example.com"))).await;
+    let body = body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Lineare%20Algebra",
+    )
+    .await;
+    assert!(body.contains("UID:1-1001@calendar.example.com\r\n"));
+    let uids: HashSet<_> = body
+        .lines()
+        .filter(|line| line.starts_with("UID:"))
+        .collect();
+    assert_eq!(uids.len(), 3);
+    assert!(!body.contains("matse.morbatex.com"));
+}
+
+#[rocket::async_test]
+async fn revisions_only_change_with_content() {
+    let revisions = Revisions::default();
+    let retention = TimeDelta::days(14);
+    let first = revisions
+        .update(&[("a".into(), 1), ("b".into(), 2)], retention)
+        .await;
+    assert_eq!(first[0].sequence, 0);
+    let second = revisions
+        .update(&[("a".into(), 1), ("b".into(), 3)], retention)
+        .await;
+    assert_eq!(second[0].modified, first[0].modified);
+    assert_eq!(second[0].sequence, 0);
+    assert!(second[1].modified >= first[1].modified);
+    assert_eq!(second[1].sequence, 1);
+}
+
+#[rocket::async_test]
+async fn revisions_are_persisted() {
+    let dir = temp_dir("revisions_are_persisted");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("revisions.json");
+    let retention = TimeDelta::days(14);
+    let first = Revisions::load(path.clone())
+        .unwrap()
+        .update(&[("a".into(), 1)], retention)
+        .await;
+    let second = Revisions::load(path.clone())
+        .unwrap()
+        .update(&[("a".into(), 1)], retention)
+        .await;
+    assert_eq!(second[0].modified, first[0].modified);
+
+    // events not published for longer than the retention are forgotten
+    let stale = json::json!({
+        "b": {
+            "fingerprint": 2,
+            "modified": "2020-01-01T00:00:00",
+            "sequence": 3,
+            "seen": "2020-01-01",
+        },
+    });
+    fs::write(&path, stale.to_string()).unwrap();
+    Revisions::load(path.clone())
+        .unwrap()
+        .update(&[("a".into(), 1)], retention)
+        .await;
+    let stored: Value = json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    assert!(stored.get("a").is_some());
+    assert!(stored.get("b").is_none());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn current_semester_follows_boundaries() {
+    let current = |date: &str| {
+        Semester::current(
+            NaiveDate::from_str(date).unwrap(),
+            &SemesterWindows::default(),
+        )
+        .to_string()
+    };
+    assert_eq!(current("2024-02-29"), "Wintersemester 2023/24");
+    assert_eq!(current("2024-03-01"), "Sommersemester 2024");
+    assert_eq!(current("2024-08-31"), "Sommersemester 2024");
+    assert_eq!(current("2024-09-01"), "Wintersemester 2024/25");
+    assert_eq!(current("2024-12-31"), "Wintersemester 2024/25");
+    assert_eq!(winter_2023().next().to_string(), "Sommersemester 2024");
+    assert_eq!(
+        winter_2023().next().next().to_string(),
+        "Wintersemester 2024/25"
+    );
+}
+
+#[test]
+fn semester_windows_are_configurable() {
+    let config: ScheduleConfig = figment("http://localhost/")
+        .merge((
+            "semester_windows",
+            json::json!({
+                "winter": ["08-15", "03-31"],
+                "overrides": [{
+                    "year": 2024,
+                    "winter_semester": false,
+                    "start": "2024-02-01",
+                    "end": "2024-10-01",
+                }],
+            }),
+        ))
+        .extract()
+        .unwrap();
+    let windows = &config.semester_windows;
+    let window = |year, winter_semester| windows.window(year, winter_semester).unwrap();
+    let date = |date: &str| NaiveDate::from_str(date).unwrap();
+    assert_eq!(window(2023, true), (date("2023-08-15"), date("2024-03-31")));
+    assert_eq!(
+        window(2023, false),
+        (date("2023-03-01"), date("2023-09-15"))
+    );
+    assert_eq!(
+        window(2024, false),
+        (date("2024-02-01"), date("2024-10-01"))
+    );
+    assert_eq!(
+        Semester::current(date("2024-08-20"), windows).to_string(),
+        "Wintersemester 2024/25"
+    );
+
+    let leap: ScheduleConfig = figment("http://localhost/")
+        .merge((
+            "semester_windows",
+            json::json!({ "winter": ["09-01", "02-29"] }),
+        ))
+        .extract()
+        .unwrap();
+    let windows = &leap.semester_windows;
+    assert_eq!(
+        windows.window(2023, true),
+        Some((date("2023-09-01"), date("2024-02-29")))
+    );
+    assert_eq!(
+        windows.window(2024, true),
+        Some((date("2024-09-01"), date("2025-02-28")))
+    );
+    let range = Semesters::Range {
+        from: date("2024-01-01"),
+        to: date("2025-12-31"),
+    };
+    let semesters: Vec<_> = range
+        .feeds(&leap)
+        .unwrap()
+        .iter()
+        .map(|feeds| feeds.semester.to_string())
+        .collect();
+    assert_eq!(
+        semesters,
+        [
+            "Wintersemester 2023/24",
+            "Sommersemester 2024",
+            "Wintersemester 2024/25",
+            "Sommersemester 2025",
+            "Wintersemester 2025/26",
+        ]
+    );
+    let unrepresentable = Semesters::Range {
+        from: NaiveDate::MAX,
+        to: NaiveDate::MAX,
+    };
+    assert!(matches!(
+        unrepresentable.feeds(&leap),
+        Err(Error::InvalidSemester)
+    ));
+
+    for windows in [
+        json::json!({ "summer": ["02-30", "09-15"] }),
+        json::json!({ "overrides": [{
+            "year": 2024,
+            "winter_semester": false,
+            "start": "2024-10-01",
+            "end": "2024-02-01",
+        }] }),
+        json::json!({ "overrides": [{
+            "year": 2024,
+            "winter_semester": false,
+            "start": "2024-02-01",
+            "end": "2034-02-01",
+        }] }),
+    ] {
+        let invalid = figment("http://localhost/")
+            .merge(("semester_windows", windows))
+            .extract::<ScheduleConfig>();
+        assert!(invalid.is_err());
+    }
+}
+
+#[rocket::async_test]
+async fn calendar_of_date_range() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(&client, "/calendar?from=2023-10-03&to=2023-10-04&curses=Analysis%20%C3%9Cbung&curses=Lineare%20Algebra").await;
+    assert_eq!(body.matches("SUMMARY:Lineare Algebra").count(), 2);
+    assert!(!body.contains("Analysis Übung"));
+    let requests = feed.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests.contains(&"/eventFeed/2?start=2023-09-01&end=2024-03-15".to_string()));
+
+    for (uri, message) in [
+        ("/calendar?from=2023-10-04&to=2023-10-03", "after"),
+        ("/calendar?from=2023-10-04&to=2033-10-03", "days apart"),
+        ("/calendar?from=2023-10-04", "together"),
+    ] {
+        let body = error_body(&client, uri, Status::UnprocessableEntity).await;
+        assert_eq!(body["error"], "invalid_query");
+        assert!(body["message"].as_str().unwrap().contains(message));
+    }
+}
+
+#[rocket::async_test]
+async fn calendar_of_current_semester_includes_next() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(
+        &client,
+        "/calendar?current=true&include_next=true&curses=Analysis",
+    )
+    .await;
+    // both semesters serve the same fixture, events of the overlap are only included once
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 1);
+    let windows = SemesterWindows::default();
+    let current = Semester::current(today(), &windows);
+    let starts: Vec<_> = [current.clone(), current.next()]
+        .iter()
+        .map(|semester| {
+            format!(
+                "/eventFeed/1?start={}&",
+                semester.get_start_date(&windows).unwrap()
+            )
+        })
+        .collect();
+    let requests = feed.requests();
+    assert!(starts
+        .iter()
+        .all(|start| requests.iter().any(|request| request.starts_with(start))));
+
+    let body = error_body(
+        &client,
+        "/calendar?curses=Analysis",
+        Status::UnprocessableEntity,
+    )
+    .await;
+    assert_eq!(body["error"], "invalid_query");
+}
+
+#[test]
+fn cohort_follows_academic_years() {
+    let config = ScheduleConfig::default();
+    let feeds = Semesters::Cohort { start_year: 2023 }
+        .feeds(&config)
+        .unwrap();
+    let feeds: Vec<_> = feeds
+        .iter()
+        .map(|feeds| {
+            let ids: Vec<_> = feeds
+                .academic_years
+                .iter()
+                .map(|academic_year| academic_year.id.as_str())
+                .collect();
+            format!("{}: {}", feeds.semester, ids.join(", "))
+        })
+        .collect();
+    assert_eq!(
+        feeds,
+        [
+            "Wintersemester 2023/24: 1, 4",
+            "Sommersemester 2024: 1, 4",
+            "Wintersemester 2024/25: 2, 4",
+            "Sommersemester 2025: 2, 4",
+            "Wintersemester 2025/26: 3, 4",
+            "Sommersemester 2026: 3, 4",
+        ]
+    );
+
+    let accepts = |cohort_year: u8| {
+        figment("http://localhost/")
+            .merge((
+                "academic_years",
+                json::json!([{ "id": "1", "name": "1. Lehrjahr", "cohort_year": cohort_year }]),
+            ))
+            .extract::<ScheduleConfig>()
+            .is_ok()
+    };
+    assert!(accepts(6));
+    assert!(!accepts(0));
+    assert!(!accepts(7));
+}
+
+#[rocket::async_test]
+async fn cohort_calendar_stitches_semesters() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?cohort=2023&academic_year_curses=1:Analysis&academic_year_curses=2:Lineare%20Algebra")
+        .dispatch()
+        .await;
+    // the mock serves the autumn 2023 fixtures for every semester, so only the first semester
+    // keeps them and the second academic year has no events in its semesters
+    assert_eq!(
+        response.headers().get_one("Warning"),
+        Some(r#"299 - "curses matched no events: 2:Lineare%20Algebra""#)
+    );
+    let body = response.into_string().await.unwrap();
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 1);
+    assert!(body.contains("UID:1-1001@matse.morbatex.com\r\n"));
+    let requests = feed.requests();
+    assert_eq!(requests.len(), 12);
+    assert!(requests.contains(&"/eventFeed/3?start=2025-09-01&end=2026-03-15".to_string()));
+    assert!(!requests
+        .iter()
+        .any(|request| request.starts_with("/eventFeed/2?start=2023")));
+}
+
+#[rocket::async_test]
+async fn subscriptions_are_persisted() {
+    let dir = temp_dir("subscriptions_are_persisted");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("subscriptions.json");
+    let query = "winter_semester=true&year=2023&curses=Analysis";
+    let token = Subscriptions::load(path.clone())
+        .unwrap()
+        .insert(query, 1)
+        .await
+        .unwrap();
+    let subscriptions = Subscriptions::load(path.clone()).unwrap();
+    assert_eq!(subscriptions.get(&token).as_deref(), Some(query));
+    assert_eq!(subscriptions.insert(query, 1).await.unwrap(), token);
+    let other = "winter_semester=true&year=2023&curses=Lineare%20Algebra";
+    assert!(matches!(
+        subscriptions.insert(other, 1).await,
+        Err(Error::SubscriptionsUnavailable(_))
+    ));
+
+    // another query stored under the same token, as after a hash collision
+    fs::write(&path, json::json!({ token.clone(): other }).to_string()).unwrap();
+    let subscriptions = Subscriptions::load(path.clone()).unwrap();
+    let rehashed = subscriptions.insert(query, 2).await.unwrap();
+    assert_ne!(rehashed, token);
+    assert_eq!(subscriptions.insert(query, 2).await.unwrap(), rehashed);
+    let subscriptions = Subscriptions::load(path).unwrap();
+    assert_eq!(subscriptions.get(&token).as_deref(), Some(other));
+    assert_eq!(subscriptions.get(&rehashed).as_deref(), Some(query));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn subscription_link_serves_calendar() {
+    let dir = temp_dir("subscription_link_serves_calendar");
+    let feed = MockFeed::standard();
+    let client = client(figment(&feed.url).merge(("cache_dir", &dir))).await;
+    let query =
+        "winter_semester=true&year=2023&curses=Analysis&curses=Lineare%20Algebra&local_time=true";
+    let response = client.post("/subscriptions").body(query).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let link = response.headers().get_one("Location").unwrap().to_string();
+    assert!(link.starts_with("/c/") && link.ends_with(".ics"));
+    assert_eq!(response.into_string().await.unwrap(), link);
+
+    let response = client.get(&link).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::Calendar));
+    let subscribed = response.into_string().await.unwrap();
+    let direct = body(&client, &format!("/calendar?{query}")).await;
+    assert_eq!(subscribed, direct);
+    assert!(subscribed.contains("BEGIN:VTIMEZONE"));
+
+    let again = client.post("/subscriptions").body(query).dispatch().await;
+    assert_eq!(again.headers().get_one("Location"), Some(link.as_str()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn subscription_errors() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .post("/subscriptions")
+        .body("winter_semester=true&curses=Analysis")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["error"], "invalid_query");
+
+    let body = error_body(&client, "/c/0123456789abcdef.ics", Status::NotFound).await;
+    assert_eq!(body["error"], "unknown_subscription");
+
+    // without cache_dir the links would not survive a restart
+    let response = client
+        .post("/subscriptions")
+        .body("winter_semester=true&year=2023&curses=Analysis")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["message"], "subscriptions require cache_dir");
+}
+
+#[rocket::async_test]
+async fn calendar_bumps_sequence_of_changed_events() {
+    let feed = MockFeed::standard();
+    let client = client(figment(&feed.url).merge(("cache_ttl", 0))).await;
+    let uri =
+        "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung";
+    let first = body(&client, uri).await;
+    assert_eq!(first.matches("SEQUENCE:0\r\n").count(), 2);
+    assert_eq!(first.matches("LAST-MODIFIED:").count(), 2);
+
+    feed.respond("1", 200, YEAR_1_RESCHEDULED);
+    let second = body(&client, uri).await;
+    assert!(second.contains("UID:1-1001@matse.morbatex.com\r\n"));
+    assert!(second.contains("DTSTART:20231005T080000Z\r\n"));
+    assert_eq!(second.matches("SEQUENCE:0\r\n").count(), 1);
+    assert_eq!(second.matches("SEQUENCE:1\r\n").count(), 1);
+    // the unchanged exercise keeps its stamp
+    let stamp = |body: &str| {
+        body.split("BEGIN:VEVENT")
+            .find(|event| event.contains("SUMMARY:Analysis Übung"))
+            .and_then(|event| event.lines().find(|line| line.starts_with("DTSTAMP:")))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(stamp(&first), stamp(&second));
+}
+
+#[rocket::async_test]
+async fn academic_year_events_are_fetched_for_semester() {
+    let feed = MockFeed::with_year_1(YEAR_1);
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::zero());
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert_eq!(
+        feed.requests(),
+        ["/eventFeed/1?start=2023-09-01&end=2024-03-15"]
+    );
+}
+
+#[rocket::async_test]
+async fn unknown_academic_year_is_an_error() {
+    let feed = MockFeed::start(&[]);
+    let config = feed.config();
+    let url = config.feed_url(&config.academic_years[3]).unwrap();
+    assert_eq!(
+        fetch_academic_year_feed(
+            url,
+            NaiveDate::from_ymd_opt(2023, 9, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 3, 15).unwrap()
+        )
+        .await
+        .err(),
+        Some(Error::Status(404))
+    );
+}
+
+#[rocket::async_test]
+async fn cache_serves_fresh_events_without_refetching() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::from_secs(900), TimeDelta::days(14));
+    for _ in 0..2 {
+        let events = get_year_1(&config, &cache).await.unwrap();
+        assert_eq!(events.value.len(), 3);
+        assert!(!events.stale);
+    }
+    assert_eq!(feed.requests().len(), 1);
+}
+
+#[rocket::async_test]
+async fn cache_serves_stale_events_when_refresh_fails() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::zero());
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 500, "");
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert!(events.stale);
+    feed.respond("1", 200, YEAR_2);
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert!(!events.stale);
+}
+
+#[rocket::async_test]
+async fn cache_does_not_keep_failures() {
+    let feed = MockFeed::start(&[("1", 500, "")]);
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::from_secs(900), TimeDelta::days(14));
+    assert!(get_year_1(&config, &cache).await.is_err());
+    feed.respond("1", 200, YEAR_1);
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+}
+
+#[rocket::async_test]
+async fn cache_keeps_events_of_ended_semester_dropped_upstream() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::days(14));
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 200, EMPTY);
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert!(!events.stale);
+}
+
+#[rocket::async_test]
+async fn persisted_cache_survives_restart() {
+    let dir = temp_dir("persisted_cache_survives_restart");
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache =
+        FeedCache::load(Duration::from_secs(900), TimeDelta::days(14), dir.clone()).unwrap();
+    get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    feed.respond("1", 500, "");
+    let cache =
+        FeedCache::load(Duration::from_secs(900), TimeDelta::days(14), dir.clone()).unwrap();
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert!(!events.stale);
+    assert_eq!(feed.requests().len(), 1);
+
+    let cache = FeedCache::load(Duration::ZERO, TimeDelta::days(14), dir.clone()).unwrap();
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert_eq!(events.value.len(), 3);
+    assert!(events.stale);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn removed_events_are_served_as_cancelled() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::days(14));
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 200, YEAR_2);
+    let events = get_year_1(&config, &cache).await.unwrap().value;
+    let mut cancelled: Vec<_> = events
+        .iter()
+        .filter(|event| event.is_cancelled)
+        .map(|event| event.name.as_str())
+        .collect();
+    cancelled.sort();
+    assert_eq!(cancelled, ["Analysis", "Analysis Übung"]);
+    assert_eq!(events.len(), 5);
+
+    // events coming back are no longer cancelled, the parallel groups now are
+    feed.respond("1", 200, YEAR_1);
+    let events = get_year_1(&config, &cache).await.unwrap().value;
+    assert_eq!(events.len(), 5);
+    assert!(events
+        .iter()
+        .all(|event| event.is_cancelled == (event.name == "Lineare Algebra")));
+}
+
+#[rocket::async_test]
+async fn rescheduled_events_are_not_cancelled() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::days(14));
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 200, YEAR_1_RESCHEDULED);
+    let events = get_year_1(&config, &cache).await.unwrap().value;
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|event| !event.is_cancelled));
+}
+
+#[rocket::async_test]
+async fn cancellations_expire_after_grace_period() {
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::new(Duration::ZERO, TimeDelta::zero());
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 200, YEAR_2);
+    let events = get_year_1(&config, &cache).await.unwrap().value;
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|event| !event.is_cancelled));
+}
+
+#[rocket::async_test]
+async fn persisted_cancellations_survive_restart() {
+    let dir = temp_dir("persisted_cancellations_survive_restart");
+    let feed = MockFeed::standard();
+    let config = feed.config();
+    let cache = FeedCache::load(Duration::ZERO, TimeDelta::days(14), dir.clone()).unwrap();
+    get_year_1(&config, &cache).await.unwrap();
+    feed.respond("1", 200, YEAR_2);
+    get_year_1(&config, &cache).await.unwrap();
+
+    feed.respond("1", 500, "");
+    let cache = FeedCache::load(Duration::ZERO, TimeDelta::days(14), dir.clone()).unwrap();
+    let events = get_year_1(&config, &cache).await.unwrap();
+    assert!(events.stale);
+    assert_eq!(
+        events
+            .value
+            .iter()
+            .filter(|event| event.is_cancelled)
+            .count(),
+        2
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[rocket::async_test]
+async fn calendar_marks_cancelled_events() {
+    let feed = MockFeed::standard();
+    let client = client(figment(&feed.url).merge(("cache_ttl", 0))).await;
+    let uri = "/calendar?winter_semester=true&year=2023&curses=Analysis";
+    let first = body(&client, uri).await;
+    assert!(!first.contains("STATUS:CANCELLED"));
+
+    feed.respond("1", 200, YEAR_2);
+    let second = body(&client, uri).await;
+    assert!(second.contains("UID:1-1001@matse.morbatex.com\r\n"));
+    assert!(second.contains("STATUS:CANCELLED\r\n"));
+    assert!(second.contains("SEQUENCE:1\r\n"));
+    assert!(second.contains("DESCRIPTION:Dieser Termin entfällt"));
+    assert!(second.contains("Skript online"));
+
+    let categories = body(&client, "/eventCategories?winter_semester=true&year=2023").await;
+    assert!(!categories.contains("Analysis"));
+}
+
+#[rocket::async_test]
+async fn stale_calendar_has_warning_header() {
+    let feed = MockFeed::standard();
+    let client = client(figment(&feed.url).merge(("cache_ttl", 0))).await;
+    let uri = "/calendar?winter_semester=true&year=2023&curses=Analysis";
+    let response = client.get(uri).dispatch().await;
+    assert_eq!(response.headers().get_one("Warning"), None);
+    feed.respond("2", 503, "");
+    let response = client.get(uri).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Warning"),
+        Some("110 - \"Response is Stale\"")
+    );
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("SUMMARY:Analysis\r\n"));
+}
+
+#[rocket::async_test]
+async fn upstream_status_is_bad_gateway() {
+    let feed = MockFeed::start(&[("1", 500, "")]);
+    let client = feed.client().await;
+    let body = error_body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=Analysis",
+        Status::BadGateway,
+    )
+    .await;
+    assert_eq!(body["error"], "upstream_status");
+    assert_eq!(body["upstream_status"], 500);
+}
+
+#[rocket::async_test]
+async fn invalid_upstream_json_is_bad_gateway() {
+    let feed = MockFeed::start(&[("1", 200, r#"[{"title": "Analysis"}]"#)]);
+    let client = feed.client().await;
+    let body = error_body(
+        &client,
+        "/eventCategories?winter_semester=true&year=2023",
+        Status::BadGateway,
+    )
+    .await;
+    assert_eq!(body["error"], "upstream_invalid_response");
+    assert!(body.get("upstream_status").is_none());
+}
+
+#[rocket::async_test]
+async fn unreachable_upstream_is_service_unavailable() {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let client = client(figment(&format!("http://{address}/eventFeed/"))).await;
+    let body = error_body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=Analysis",
+        Status::ServiceUnavailable,
+    )
+    .await;
+    assert_eq!(body["error"], "upstream_unreachable");
+}
+
+#[rocket::async_test]
+async fn calendar_contains_selected_curses() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Lineare%20Algebra")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::Calendar));
+    let body = response.into_string().await.unwrap();
+    assert!(body.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 3);
+    assert!(body.contains("SUMMARY:Analysis\r\n"));
+    assert!(body.contains("SUMMARY:Lineare Algebra\r\n"));
+    assert!(!body.contains("Analysis Übung"));
+    assert!(body.contains("DTSTART:20231002T060000Z\r\n"));
+    assert!(body.contains("DTEND:20231002T073000Z\r\n"));
+    assert!(body.contains("DESCRIPTION:Bitte Laptop mitbringen\\nSkript online\r\n"));
+    assert!(body.contains("CATEGORIES:LECTURE\r\n"));
+    assert!(body.contains("MAILTO:algebra@example.com\r\n"));
+}
+
+#[rocket::async_test]
+async fn calendar_with_local_time_uses_tzid() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(&client, "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung&local_time=true").await;
+    assert!(body.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/Berlin\r\n"));
+    assert!(body.contains("DTSTART:20231029T030000\r\n"));
+    assert!(body.contains("DTSTART;TZID=Europe/Berlin:20231002T080000\r\n"));
+    assert!(body.contains("DTEND;TZID=Europe/Berlin:20231002T093000\r\n"));
+    assert!(body.contains("DTSTART;TZID=Europe/Berlin:20231106T090000\r\n"));
+    assert!(!body.contains("DTSTART:20231002T060000Z"));
+}
+
+#[rocket::async_test]
+async fn calendar_as_jcal() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Weihnachtsferien&format=jcal&local_time=true")
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "calendar+json"))
+    );
+    let calendar: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(calendar[0], "vcalendar");
+    assert!(calendar[1]
+        .as_array()
+        .unwrap()
+        .contains(&json::json!(["version", {}, "text", "2.0"])));
+    let components = calendar[2].as_array().unwrap();
+    assert_eq!(components[0][0], "vtimezone");
+    let analysis = components[1][1].as_array().unwrap();
+    assert!(analysis.contains(&json::json!(["summary", {}, "text", "Analysis"])));
+    assert!(analysis.contains(&json::json!([
+        "dtstart",
+        { "tzid": "Europe/Berlin" },
+        "date-time",
+        "2023-10-02T08:00:00"
+    ])));
+    assert!(analysis.contains(&json::json!([
+        "description",
+        {},
+        "text",
+        "Bitte Laptop mitbringen\nSkript online"
+    ])));
+    assert!(analysis.contains(&json::json!(["sequence", {}, "integer", 0])));
+    assert!(analysis.contains(&json::json!([
+        "organizer",
+        { "cn": "Erika Mustermann" },
+        "cal-address",
+        "MAILTO:mustermann@example.com"
+    ])));
+    let holiday = components[2][1].as_array().unwrap();
+    assert!(holiday.contains(&json::json!(["dtstart", {}, "date", "2023-12-23"])));
+}
+
+#[test]
+fn malformed_calendars_are_not_converted() {
+    for ics in [
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n",
+        "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR\r\n",
+        "BEGIN:VCALENDAR\r\nVERSION\r\nEND:VCALENDAR\r\n",
+        "BEGIN:VCALENDAR\r\nORGANIZER:CN=Erika Mustermann:MAILTO:mustermann@example.com\r\nEND:VCALENDAR\r\n",
+        "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\nBEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n",
+    ] {
+        assert!(Format::JCal.render(ics.to_string()).is_err(), "{ics}");
+        assert!(Format::XCal.render(ics.to_string()).is_err(), "{ics}");
+    }
+}
+
+#[rocket::async_test]
+async fn calendar_as_xcal_by_accept_header() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=Lineare%20Algebra")
+        .header(Accept::from_str("text/html;q=0.9, application/calendar+xml").unwrap())
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("application", "calendar+xml"))
+    );
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains(
+        r#"<icalendar xmlns="urn:ietf:params:xml:ns:icalendar-2.0"><vcalendar><properties>"#
+    ));
+    assert_eq!(body.matches("<vevent>").count(), 2);
+    assert!(body.contains("<dtstart><date-time>2023-10-04T11:00:00Z</date-time></dtstart>"));
+    assert!(body.contains("<summary><text>Lineare Algebra</text></summary>"));
+    assert!(body.ends_with("</vcalendar></icalendar>"));
+}
+
+#[rocket::async_test]
+async fn calendar_as_csv() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar.csv?winter_semester=true&year=2023&curses=Weihnachtsferien&curses=Analysis&curses=Analysis%20%C3%9Cbung")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    let body = response.into_string().await.unwrap();
+    let rows: Vec<_> = body.split_terminator("\r\n").collect();
+    assert_eq!(
+        rows,
+        [
+            "Datum;Wochentag;Beginn;Ende;Veranstaltung;Art;Raum;Dozent",
+            "02.10.2023;Montag;08:00;09:30;Analysis;Vorlesung;Hörsaal 1, Templergraben 55, Erdgeschoss;Erika Mustermann",
+            "06.11.2023;Montag;09:00;10:30;Analysis Übung;Übung;;",
+            "23.12.2023;Samstag;;06.01.2024;Weihnachtsferien;Ferien;;",
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn calendar_as_csv_with_selected_columns_in_english() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(&client, "/calendar.csv?winter_semester=true&year=2023&curses=Analysis&language=en&columns=course&columns=date&columns=room").await;
+    assert_eq!(
+        body,
+        "Course,Date,Room\r\nAnalysis,2023-10-02,\"Hörsaal 1, Templergraben 55, Erdgeschoss\"\r\n"
+    );
+}
+
+#[rocket::async_test]
+async fn timetable_collapses_weekly_slots() {
+    let feed = MockFeed::with_year_1(WEEKLY);
+    let client = feed.client().await;
+    let response = client
+        .get("/timetable?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung&curses=Tag%20der%20Deutschen%20Einheit")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    let html = response.into_string().await.unwrap();
+    assert!(html.contains("<title>Stundenplan Wintersemester 2023/24</title>"));
+    assert!(html.contains("@media print"));
+    assert!(html.contains(
+        "<th>Montag</th><th>Dienstag</th><th>Mittwoch</th><th>Donnerstag</th><th>Freitag</th></tr>"
+    ));
+    assert_eq!(html.matches("class=\"slot\"").count(), 2);
+    assert!(html.contains(
+        "<div class=\"time\">08:00–09:30</div><div>Analysis</div><div>Hörsaal 1</div>\
+         <div class=\"note\">4× vom 02.10. bis 30.10.2023</div>\
+         <div class=\"note\">entfällt am 16.10.</div>"
+    ));
+    assert!(html.contains("<div>Seminarraum &lt;B&gt;</div>"));
+    // the moved session is listed on its own
+    assert!(html.contains("<li>17.10.2023, 10:00–11:30: Analysis (Hörsaal 1)</li>"));
+    assert!(html.contains("<li>03.10.2023: Tag der Deutschen Einheit</li>"));
+}
+
+#[test]
+fn weekly_events_collapse_into_series() {
+    let mut events = parse_events(WEEKLY).unwrap();
+    // without the moved session the week is just skipped
+    events.remove(4);
+    let items = recurrence::collapse(events);
+    assert_eq!(items.len(), 3);
+    let Item::Series(analysis) = &items[0] else {
+        panic!("Analysis is not a series");
+    };
+    assert_eq!(analysis.event.get_start_date(), "20231002T060000Z");
+    assert_eq!(
+        analysis.until.naive_utc().to_string(),
+        "2023-10-30 07:00:00"
+    );
+    assert_eq!(
+        analysis.excluded,
+        ["2023-10-16T08:00:00".parse::<NaiveDateTime>().unwrap()]
+    );
+    assert!(analysis.overrides.is_empty());
+    assert!(matches!(&items[1], Item::Series(series) if series.excluded.is_empty()));
+    assert!(matches!(&items[2], Item::Single(event) if event.is_holiday));
+}
+
+#[rocket::async_test]
+async fn calendar_with_recurring_series() {
+    let feed = MockFeed::with_year_1(WEEKLY);
+    let client = feed.client().await;
+    let uri = "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung&recurring=true";
+    let calendar = body(&client, uri).await;
+    assert!(calendar.contains("BEGIN:VTIMEZONE"));
+    assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 3);
+    assert_eq!(
+        calendar
+            .matches("UID:1-3001@matse.morbatex.com\r\n")
+            .count(),
+        2
+    );
+    assert!(calendar.contains("DTSTART;TZID=Europe/Berlin:20231002T080000\r\n"));
+    assert!(calendar.contains("RRULE:FREQ=WEEKLY;UNTIL=20231030T070000Z\r\n"));
+    // the session moved to Tuesday replaces the one of that week
+    assert!(!calendar.contains("EXDATE"));
+    assert!(calendar.contains("RECURRENCE-ID;TZID=Europe/Berlin:20231016T080000\r\n"));
+    assert!(calendar.contains("DTSTART;TZID=Europe/Berlin:20231017T100000\r\n"));
+    assert!(calendar.contains("RRULE:FREQ=WEEKLY;UNTIL=20231011T110000Z\r\n"));
+
+    let jcal: Value = json::from_str(&body(&client, &format!("{uri}&format=jcal")).await).unwrap();
+    assert!(jcal[2][1][1].as_array().unwrap().contains(&json::json!([
+        "rrule",
+        {},
+        "recur",
+        { "freq": "WEEKLY", "until": "2023-10-30T07:00:00Z" }
+    ])));
+}
+
+#[rocket::async_test]
+async fn calendar_with_alarms() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let uri = "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung&curses=Weihnachtsferien&alarm=15&alarm_exercise=5";
+    let calendar = body(&client, uri).await;
+    // holidays are excluded unless requested
+    assert_eq!(calendar.matches("BEGIN:VALARM").count(), 2);
+    assert!(calendar.contains(
+        "BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nDESCRIPTION:Analysis\r\nEND:VALARM"
+    ));
+    assert!(calendar.contains("TRIGGER:-PT5M\r\nDESCRIPTION:Analysis Übung\r\n"));
+
+    let calendar = body(&client, &format!("{uri}&alarm_holiday=1440")).await;
+    assert_eq!(calendar.matches("TRIGGER:-PT1440M").count(), 1);
+}
+
+#[test]
+fn exam_alarms_are_recognized_by_name() {
+    let mut events = parse_events(YEAR_1).unwrap();
+    let options = CalendarOptions {
+        alarm: Optional(Some(10)),
+        alarm_exam: Optional(Some(60)),
+        ..CalendarOptions::default()
+    };
+    assert_eq!(options.alarm(&events[0]), Some(10));
+    events[0].name = "Analysis Klausur".into();
+    assert_eq!(options.alarm(&events[0]), Some(60));
+    events[0].is_cancelled = true;
+    assert_eq!(options.alarm(&events[0]), None);
+    assert_eq!(options.alarm(&events[2]), None);
+}
+
+#[rocket::async_test]
+async fn calendar_filtered_by_event_type() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let calendar = body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&types=holiday",
+    )
+    .await;
+    // the holidays of every academic year without naming them, each only once
+    assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 1);
+    assert_eq!(calendar.matches("SUMMARY:Weihnachtsferien").count(), 1);
+
+    let calendar = body(&client, "/calendar?winter_semester=true&year=2023&curses=Analysis&curses=Analysis%20%C3%9Cbung&types=lecture&exclude_types=exercise").await;
+    // types narrow down the curses instead of adding the unselected lectures
+    assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 1);
+    assert!(calendar.contains("SUMMARY:Analysis\r\n"));
+    assert!(!calendar.contains("Lineare Algebra"));
+    assert!(!calendar.contains("Analysis Übung"));
+}
+
+#[test]
+fn selection_includes_holidays_of_academic_years() {
+    let events = |holiday: &str| {
+        let mut events = Vec::new();
+        for (academic_year, feed) in [("1", YEAR_1), ("2", YEAR_2)] {
+            for mut event in parse_events(feed).unwrap() {
+                event.academic_year = academic_year.into();
+                if academic_year == "2" && event.is_holiday {
+                    event.name = holiday.into();
+                }
+                events.push(event);
+            }
+        }
+        events
+    };
+    let names = |selection: &Selection, holiday: &str| {
+        selection
+            .apply(events(holiday))
+            .into_iter()
+            .map(|event| format!("{} {}", event.academic_year, event.name))
+            .collect::<Vec<_>>()
+    };
+    let mut selection = Selection {
+        curses: vec![Selector::parse("Analysis").unwrap()],
+        include_holidays: Some(HolidayScope::Selected),
+        ..Selection::default()
+    };
+    assert_eq!(
+        names(&selection, "Winterpause"),
+        ["1 Analysis", "1 Weihnachtsferien"]
+    );
+    selection.include_holidays = Some(HolidayScope::All);
+    assert_eq!(
+        names(&selection, "Winterpause"),
+        ["1 Analysis", "1 Weihnachtsferien", "2 Winterpause"]
+    );
+    // holidays listed by several academic years are only included once
+    assert_eq!(
+        names(&selection, "Weihnachtsferien"),
+        ["1 Analysis", "1 Weihnachtsferien"]
+    );
+    selection.exclude_types = vec![EventType::Holiday];
+    assert_eq!(names(&selection, "Winterpause"), ["1 Analysis"]);
+}
+
+#[rocket::async_test]
+async fn calendar_includes_holidays() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let calendar = body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=Lineare%20Algebra&include_holidays=true",
+    )
+    .await;
+    assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 3);
+    assert!(calendar.contains("UID:2-1003@matse.morbatex.com\r\n"));
+
+    let excluded = body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=Lineare%20Algebra&include_holidays=false",
+    )
+    .await;
+    assert_eq!(excluded.matches("BEGIN:VEVENT").count(), 2);
+    assert!(!excluded.contains("Weihnachtsferien"));
+}
+
+#[test]
+fn selectors_match_normalized_names() {
+    let matches = |selector: &str, name: &str| Selector::parse(selector).unwrap().matches(name);
+    assert!(matches(" analysis  übung ", "Analysis Übung"));
+    assert!(matches("Analysis \u{2013} Teil 2", "ANALYSIS - Teil 2"));
+    assert!(matches("Übung", "U\u{308}bung"));
+    assert!(!matches("Analysis", "Analysis Übung"));
+    assert!(matches("prefix:analysis", "Analysis Übung"));
+    assert!(!matches("prefix:übung", "Analysis Übung"));
+    assert!(matches("glob:*übung", "(!) Analysis Übung"));
+    assert!(matches("glob:lineare algebra ?", "Lineare Algebra 2"));
+    assert!(!matches("glob:lineare algebra ?", "Lineare Algebra 10"));
+    assert!(matches("regex:^Analysis( Übung)?$", "Analysis Übung"));
+    assert!(!matches("regex:^analysis", "Analysis"));
+    assert!(Selector::parse("regex:(").is_err());
+}
+
+#[rocket::async_test]
+async fn calendar_selects_curses_by_pattern() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(
+        &client,
+        "/calendar?winter_semester=true&year=2023&curses=glob:*%C3%BCbung&curses=prefix:lineare",
+    )
+    .await;
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 3);
+    assert!(body.contains("Analysis Übung"));
+    assert!(!body.contains("SUMMARY:Analysis\r\n"));
+
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=regex:(")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn calendar_warns_about_unmatched_curses() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=analysis&curses=Numerik%20%C3%9Cbung&curses=prefix:Stochastik")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get("Warning").collect::<Vec<_>>(),
+        [r#"299 - "curses matched no events: Numerik%20%C3%9Cbung, prefix:Stochastik""#]
+    );
+
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=Analysis")
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Warning"), None);
+}
+
+#[rocket::async_test]
+async fn calendar_warns_if_no_curses_exist() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/calendar?winter_semester=true&year=2023&curses=Numerik&academic_year_curses=1:Lineare%20Algebra")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Warning"),
+        Some(
+            r#"299 - "none of the curses exist in Wintersemester 2023/24: Numerik, 1:Lineare%20Algebra""#
+        )
+    );
+}
+
+#[rocket::async_test]
+async fn invalid_requests_are_json_errors() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    for uri in [
+        "/calendar?winter_semester=true&year=99999&curses=Analysis",
+        "/calendar?winter_semester=true&year=-1&curses=Analysis",
+        "/calendar?cohort=1899&curses=Analysis",
+        "/calendar?from=1999-12-31&to=2000-01-01",
+    ] {
+        let body = error_body(&client, uri, Status::UnprocessableEntity).await;
+        assert_eq!(body["error"], "invalid_query");
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .contains("between 2000 and 2100"));
+    }
+    for uri in [
+        "/calendar?winter_semester=true&year=2023&current=true&curses=Analysis",
+        "/calendar?cohort=2023&year=2020&curses=Analysis",
+        "/calendar?from=2023-10-04&year=2023",
+        "/calendar?cohort=2023&include_next=true&curses=Analysis",
+        "/calendar?from=2023-10-03&to=2023-10-04&include_next=true",
+    ] {
+        let body = error_body(&client, uri, Status::UnprocessableEntity).await;
+        assert_eq!(body["error"], "invalid_query", "{uri}");
+    }
+    for (uri, field) in [
+        ("/eventCategories?winter_semester=true&year=99999", "year"),
+        ("/eventCategories?winter_semester=true", "year"),
+        (
+            "/calendar?winter_semester=true&year=2023&types=seminar",
+            "types",
+        ),
+        (
+            "/calendar?winter_semester=true&year=2023&alarm=abc",
+            "alarm",
+        ),
+        ("/calendar?winter_semester=true&year=abc", "year"),
+        ("/calendar?from=2023-13-01&to=2023-12-31", "from"),
+        (
+            "/calendar.csv?winter_semester=true&year=2023&columns=foo",
+            "columns",
+        ),
+        (
+            "/events?winter_semester=true&year=2023&include_holidays=none",
+            "include_holidays",
+        ),
+    ] {
+        let body = error_body(&client, uri, Status::UnprocessableEntity).await;
+        assert_eq!(body["error"], "invalid_query");
+        let message = body["message"].as_str().unwrap();
+        assert!(message.contains(&format!("{field}: ")), "{uri}: {message}");
+    }
+    let body = error_body(&client, "/calendars", Status::NotFound).await;
+    assert_eq!(body["error"], "not_found");
+    assert!(feed.requests().is_empty());
+}
+
+#[rocket::async_test]
+async fn events_exclude_types() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let events: Value = json::from_str(
+        &body(
+            &client,
+            "/events?winter_semester=true&year=2023&exclude_types=holiday&exclude_types=exercise",
+        )
+        .await,
+    )
+    .unwrap();
+    let names: Vec<_> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Analysis", "Lineare Algebra", "Lineare Algebra"]);
+}
+
+#[test]
+fn format_negotiation_defaults_to_icalendar() {
+    let negotiate = |accept: &str| Format::negotiate(Some(&Accept::from_str(accept).unwrap()));
+    assert_eq!(Format::negotiate(None), Format::ICalendar);
+    assert_eq!(negotiate("*/*"), Format::ICalendar);
+    assert_eq!(negotiate("text/html, */*;q=0.8"), Format::ICalendar);
+    assert_eq!(negotiate("application/calendar+json"), Format::JCal);
+    assert_eq!(
+        negotiate("text/calendar;q=0.5, application/calendar+xml"),
+        Format::XCal
+    );
+}
+
+#[rocket::async_test]
+async fn calendar_encodes_holidays_and_exercises() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let body = body(&client, "/calendar?winter_semester=true&year=2023&curses=Weihnachtsferien&curses=Analysis%20%C3%9Cbung").await;
+    // the holiday is listed in two academic years, but only selected once
+    assert_eq!(body.matches("SUMMARY:Weihnachtsferien\r\n").count(), 1);
+    // all-day events span the Berlin dates, the end being exclusive
+    assert!(body.contains("DTSTART;VALUE=DATE:20231223\r\n"));
+    assert!(body.contains("DTEND;VALUE=DATE:20240107\r\n"));
+    assert!(!body.contains("DURATION"));
+    assert!(body.contains("CATEGORIES:Holiday\r\n"));
+    assert!(body.contains("SUMMARY:Analysis Übung\r\n"));
+    assert!(body.contains("CATEGORIES:Exercise\r\n"));
+    assert!(!body.contains("LOCATION"));
+    assert!(!body.contains("ORGANIZER"));
+}
+
+#[rocket::async_test]
+async fn events_are_served_as_json() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/events?winter_semester=true&year=2023&curses=Analysis")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let events: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(
+        events,
+        json::json!([{
+            "id": "1001",
+            "academicYear": "1",
+            "name": "Analysis",
+            "start": "2023-10-02T08:00:00+02:00",
+            "end": "2023-10-02T09:30:00+02:00",
+            "dstAdjusted": false,
+            "location": {
+                "name": "Hörsaal 1",
+                "street": "Templergraben",
+                "nr": "55",
+                "desc": "Erdgeschoss"
+            },
+            "lecturer": {
+                "name": "Erika Mustermann",
+                "mail": "mustermann@example.com"
+            },
+            "information": "Bitte Laptop mitbringen\nSkript online",
+            "isHoliday": false,
+            "isExercise": false,
+            "allDay": false,
+            "isLecture": true,
+            "isCancelled": false
+        }])
+    );
+}
+
+#[rocket::async_test]
+async fn events_without_curses_lists_all_events() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let events: Value =
+        json::from_str(&body(&client, "/events?winter_semester=true&year=2023").await).unwrap();
+    let names: Vec<_> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| {
+            (
+                event["academicYear"].as_str().unwrap(),
+                event["name"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("1", "Analysis"),
+            ("1", "Analysis Übung"),
+            ("1", "Weihnachtsferien"),
+            ("2", "Lineare Algebra"),
+            ("2", "Lineare Algebra"),
+        ]
+    );
+    assert_eq!(events[2]["information"], Value::Null);
+}
+
+#[rocket::async_test]
+async fn event_categories_list_curses_per_academic_year() {
+    let feed = MockFeed::standard();
+    let client = feed.client().await;
+    let response = client
+        .get("/eventCategories?winter_semester=true&year=2023")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let categories: Value = json::from_str(&response.into_string().await.unwrap()).unwrap();
+    let categories = categories.as_array().unwrap();
+    assert_eq!(categories.len(), 4);
+    assert_eq!(categories[0]["name"], "1. Lehrjahr");
+    let mut curses: Vec<_> = categories[0]["curses"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|curse| curse.as_str().unwrap())
+        .collect();
+    curses.sort();
+    assert_eq!(curses, ["Analysis", "Analysis Übung"]);
+    assert_eq!(categories[1]["curses"], json::json!(["Lineare Algebra"]));
+    assert_eq!(categories[2]["curses"], json::json!([]));
+    assert_eq!(categories[3]["name"], "Wahlpflicht");
+    assert_eq!(categories[3]["curses"], json::json!([]));
+}
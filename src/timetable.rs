@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeDelta};
+
+use crate::{format::escape_xml as escape, Event};
+
+const WEEKDAYS: [&str; 7] = [
+    "Montag",
+    "Dienstag",
+    "Mittwoch",
+    "Donnerstag",
+    "Freitag",
+    "Samstag",
+    "Sonntag",
+];
+
+const STYLE: &str = "
+body { font-family: sans-serif; font-size: 10pt; margin: 1em; }
+table { width: 100%; border-collapse: collapse; table-layout: fixed; }
+th, td { border: 1px solid #888; padding: 4px; vertical-align: top; }
+.slot { margin-bottom: 6px; padding: 4px; background: #eef; break-inside: avoid; }
+.time { font-weight: bold; }
+.note { color: #555; font-size: 9pt; }
+@page { size: A4 landscape; margin: 1cm; }
+@media print { body { margin: 0; } .slot { background: none; border: 1px solid #ccc; } }
+";
+
+/// Events taking place at the same time of the week, in the same room.
+struct Slot<'e> {
+    start: NaiveTime,
+    end: NaiveTime,
+    name: &'e str,
+    location: String,
+    dates: Vec<NaiveDate>,
+}
+
+impl Slot<'_> {
+    /// Weeks between the first and the last occurrence without one.
+    fn missing_dates(&self) -> Vec<NaiveDate> {
+        let (Some(first), Some(last)) = (self.dates.first(), self.dates.last()) else {
+            return Vec::new();
+        };
+        first
+            .iter_weeks()
+            .take_while(|date| date < last)
+            .filter(|date| !self.dates.contains(date))
+            .collect()
+    }
+}
+
+/// Renders a printable weekly timetable of `events`.
+///
+/// Events repeating at the same weekday, time and room are collapsed into one slot of the grid,
+/// weeks they skip are listed with the slot. Events that only take place once are listed below
+/// the grid, as are holidays. Cancelled events are left out.
+pub fn render(title: &str, events: &[Event]) -> String {
+    let mut slots: BTreeMap<_, Slot> = BTreeMap::new();
+    let mut holidays = Vec::new();
+    for event in events.iter().filter(|event| !event.is_cancelled) {
+        if event.is_holiday || event.is_all_day {
+            holidays.push(event);
+            continue;
+        }
+        let (start, end) = (event.period.start, event.period.end);
+        let location = event
+            .location
+            .to_string()
+            .lines()
+            .collect::<Vec<_>>()
+            .join(", ");
+        let key = (
+            start.weekday().num_days_from_monday(),
+            start.time(),
+            end.time(),
+            event.name.as_str(),
+            location.clone(),
+        );
+        slots
+            .entry(key)
+            .or_insert_with(|| Slot {
+                start: start.time(),
+                end: end.time(),
+                name: &event.name,
+                location,
+                dates: Vec::new(),
+            })
+            .dates
+            .push(start.date_naive());
+    }
+    let (weekly, single): (Vec<_>, Vec<_>) = slots
+        .into_iter()
+        .map(|((weekday, ..), mut slot)| {
+            slot.dates.sort();
+            slot.dates.dedup();
+            (weekday, slot)
+        })
+        .partition(|(_, slot)| slot.dates.len() > 1);
+
+    let days = WEEKDAYS
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| {
+            *index < 5
+                || weekly
+                    .iter()
+                    .any(|(weekday, _)| *weekday as usize == *index)
+        })
+        .collect::<Vec<_>>();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        title = escape(title)
+    );
+    html.push_str("<table>\n<tr>");
+    for (_, name) in &days {
+        html.push_str(&format!("<th>{name}</th>"));
+    }
+    html.push_str("</tr>\n<tr>");
+    for (index, _) in &days {
+        html.push_str("<td>");
+        for (_, slot) in weekly
+            .iter()
+            .filter(|(weekday, _)| *weekday as usize == *index)
+        {
+            html.push_str(&render_slot(slot));
+        }
+        html.push_str("</td>");
+    }
+    html.push_str("</tr>\n</table>\n");
+
+    if !single.is_empty() {
+        html.push_str("<h2>Einzeltermine</h2>\n<ul>\n");
+        let mut single: Vec<_> = single.into_iter().map(|(_, slot)| slot).collect();
+        single.sort_by_key(|slot| (slot.dates[0], slot.start));
+        for slot in single {
+            html.push_str(&format!(
+                "<li>{}, {}–{}: {}{}</li>\n",
+                slot.dates[0].format("%d.%m.%Y"),
+                slot.start.format("%H:%M"),
+                slot.end.format("%H:%M"),
+                escape(slot.name),
+                location_suffix(&slot.location)
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+    if !holidays.is_empty() {
+        html.push_str("<h2>Ferien und ganztägige Termine</h2>\n<ul>\n");
+        holidays.sort_by_key(|event| event.period.start);
+        for event in holidays {
+            let (first_day, end_day) = event.period.dates();
+            let last_day = end_day - TimeDelta::days(1);
+            let dates = if first_day == last_day {
+                first_day.format("%d.%m.%Y").to_string()
+            } else {
+                format!(
+                    "{}–{}",
+                    first_day.format("%d.%m.%Y"),
+                    last_day.format("%d.%m.%Y")
+                )
+            };
+            html.push_str(&format!("<li>{dates}: {}</li>\n", escape(&event.name)));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_slot(slot: &Slot) -> String {
+    let (first, last) = (slot.dates[0], slot.dates[slot.dates.len() - 1]);
+    let mut notes = vec![format!(
+        "{}× vom {} bis {}",
+        slot.dates.len(),
+        first.format("%d.%m."),
+        last.format("%d.%m.%Y")
+    )];
+    let missing = slot.missing_dates();
+    if !missing.is_empty() {
+        let dates: Vec<_> = missing
+            .iter()
+            .map(|date| date.format("%d.%m.").to_string())
+            .collect();
+        notes.push(format!("entfällt am {}", dates.join(", ")));
+    }
+    format!(
+        "<div class=\"slot\"><div class=\"time\">{}–{}</div><div>{}</div>{}{}</div>",
+        slot.start.format("%H:%M"),
+        slot.end.format("%H:%M"),
+        escape(slot.name),
+        if slot.location.is_empty() {
+            String::new()
+        } else {
+            format!("<div>{}</div>", escape(&slot.location))
+        },
+        notes
+            .iter()
+            .map(|note| format!("<div class=\"note\">{note}</div>"))
+            .collect::<String>()
+    )
+}
+
+fn location_suffix(location: &str) -> String {
+    if location.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", escape(location))
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, TimeDelta, TimeZone as _, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use ics::{properties::TzName, Daylight, Standard, TimeZone};
+
+const LOCAL_DATE_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// Whether a local time had to be adjusted because of a daylight saving time transition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Exact,
+    /// The time occurs twice when the clocks fall back, the earlier instant is used.
+    Ambiguous,
+    /// The time is skipped when the clocks spring forward, it is shifted forward by the length
+    /// of the gap.
+    Nonexistent,
+}
+
+/// Interprets a local time of `tz`, see [`Resolution`] for how DST transitions are handled.
+pub fn from_local(tz: Tz, local: &NaiveDateTime) -> (DateTime<Tz>, Resolution) {
+    match tz.from_local_datetime(local) {
+        LocalResult::Single(date) => (date, Resolution::Exact),
+        LocalResult::Ambiguous(date, _) => (date, Resolution::Ambiguous),
+        LocalResult::None => {
+            let offset = tz
+                .offset_from_utc_datetime(&(*local - TimeDelta::days(1)))
+                .fix();
+            (
+                tz.from_utc_datetime(&(*local - offset)),
+                Resolution::Nonexistent,
+            )
+        }
+    }
+}
+
+/// Formats the wall clock time of `date`, to be used with a `TZID` parameter.
+pub fn format_local(date: &DateTime<Tz>) -> String {
+    date.naive_local().format(LOCAL_DATE_FORMAT).to_string()
+}
+
+/// Builds the VTIMEZONE for `tz`, covering every offset change between `from` and `to`.
+pub fn vtimezone<'a>(tz: Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> TimeZone<'a> {
+    let offset = tz.offset_from_utc_datetime(&from.naive_utc());
+    let mut timezone = match observance(tz, from, offset, offset) {
+        Observance::Standard(standard) => TimeZone::standard(tz.name(), standard),
+        Observance::Daylight(daylight) => TimeZone::daylight(tz.name(), daylight),
+    };
+    let mut previous = offset;
+    for transition in transitions(tz, from, to) {
+        let offset = tz.offset_from_utc_datetime(&transition.naive_utc());
+        match observance(tz, transition, previous, offset) {
+            Observance::Standard(standard) => timezone.add_standard(standard),
+            Observance::Daylight(daylight) => timezone.add_daylight(daylight),
+        }
+        previous = offset;
+    }
+    timezone
+}
+
+enum Observance<'a> {
+    Standard(Standard<'a>),
+    Daylight(Daylight<'a>),
+}
+
+fn observance<'a>(
+    tz: Tz,
+    start: DateTime<Utc>,
+    from: <Tz as chrono::TimeZone>::Offset,
+    to: <Tz as chrono::TimeZone>::Offset,
+) -> Observance<'a> {
+    let dtstart = (start.naive_utc() + from.fix())
+        .format(LOCAL_DATE_FORMAT)
+        .to_string();
+    let (offset_from, offset_to) = (format_offset(from.fix()), format_offset(to.fix()));
+    let name = to.abbreviation().unwrap_or(tz.name()).to_string();
+    if to.dst_offset().is_zero() {
+        let mut standard = Standard::new(dtstart, offset_from, offset_to);
+        standard.push(TzName::new(name));
+        Observance::Standard(standard)
+    } else {
+        let mut daylight = Daylight::new(dtstart, offset_from, offset_to);
+        daylight.push(TzName::new(name));
+        Observance::Daylight(daylight)
+    }
+}
+
+/// Instants between `from` and `to` at which the offset of `tz` changes.
+fn transitions(tz: Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let offset_at = |date: DateTime<Utc>| tz.offset_from_utc_datetime(&date.naive_utc()).fix();
+    let mut transitions = Vec::new();
+    let mut day = from;
+    while day < to {
+        let next = day + TimeDelta::days(1);
+        if offset_at(day) != offset_at(next) {
+            // binary search for the first second with the new offset
+            let (mut before, mut after) = (day, next);
+            while after - before > TimeDelta::seconds(1) {
+                let middle = before + (after - before) / 2;
+                if offset_at(middle) == offset_at(day) {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            transitions.push(after);
+        }
+        day = next;
+    }
+    transitions
+}
+
+fn format_offset(offset: chrono::FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+}
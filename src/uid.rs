@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use crate::Event;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Hands out the UIDs of the events of one calendar.
+///
+/// Events with an upstream id keep it, so a rescheduled event updates the existing one in the
+/// subscribers' calendars. Otherwise the UID is derived from academic year, name, start, location
+/// and lecturer, so parallel groups keep their UIDs when one of them drops out. If two events
+/// would still end up with the same UID, the later ones get a counter appended.
+pub struct Uids<'d> {
+    domain: &'d str,
+    issued: HashMap<String, usize>,
+}
+
+impl<'d> Uids<'d> {
+    pub fn new(domain: &'d str) -> Self {
+        Self {
+            domain,
+            issued: HashMap::new(),
+        }
+    }
+
+    pub fn next(&mut self, event: &Event) -> String {
+        let base = match &event.id {
+            Some(id) => format!("{}-{id}", event.academic_year),
+            None => format!(
+                "{}-{:016x}",
+                event.academic_year,
+                fnv1a(&[
+                    &event.academic_year,
+                    &event.name,
+                    &event.get_start_date(),
+                    &event.location.to_string(),
+                    &event.lecturer.to_string(),
+                ])
+            ),
+        };
+        let count = self.issued.entry(base.clone()).or_default();
+        *count += 1;
+        match *count {
+            1 => format!("{base}@{}", self.domain),
+            count => format!("{base}-{count}@{}", self.domain),
+        }
+    }
+}
+
+/// 64 bit FNV-1a, used instead of `DefaultHasher` as UIDs and fingerprints have to be stable
+/// across releases.
+pub fn fnv1a(parts: &[&str]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.bytes().chain([0]))
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        })
+}